@@ -0,0 +1,109 @@
+use crossbeam_channel::{Receiver, Sender};
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+use std::thread;
+use std::time::Instant;
+
+use crate::data::{GuiOutputDataContainer, Packet};
+use crate::gui::GuiCommand;
+use crate::io::FileOptions;
+use crate::main_thread;
+use crate::serial::{serial_thread, Device, ModemLines, PortInfo, SerialCommand};
+use crate::transfer::TransferProgress;
+
+/// Shared state and channels of one serial connection, each connection has its own
+/// serial thread and main thread.
+#[derive(Clone)]
+pub struct Connection {
+    pub device_lock: Arc<RwLock<Device>>,
+    pub connected_lock: Arc<RwLock<bool>>,
+    pub modem_lock: Arc<RwLock<ModemLines>>,
+    pub transfer_lock: Arc<RwLock<Option<TransferProgress>>>,
+    pub data_lock: Arc<RwLock<GuiOutputDataContainer>>,
+    pub save_tx: Sender<FileOptions>,
+    pub load_tx: Sender<PathBuf>,
+    pub load_names_rx: Receiver<Vec<String>>,
+    pub send_tx: Sender<String>,
+    pub serial_cmd_tx: Sender<SerialCommand>,
+    pub gui_cmd_tx: Sender<GuiCommand>,
+}
+
+/// Spawns the threads of a new connection. All connections share `t_zero`, so that the
+/// traffic of different ports is on the same time base.
+/// The threads exit once all clones of the returned [`Connection`] have been dropped.
+pub fn start_connection(
+    device: Device,
+    t_zero: Instant,
+    devices_lock: Arc<RwLock<Vec<PortInfo>>>,
+    sync_tx: Sender<bool>,
+    column_labels: Vec<String>,
+) -> Connection {
+    let device_lock = Arc::new(RwLock::new(device));
+    let data_lock = Arc::new(RwLock::new(GuiOutputDataContainer::default()));
+    let connected_lock = Arc::new(RwLock::new(false));
+    let modem_lock = Arc::new(RwLock::new(ModemLines::default()));
+    let transfer_lock = Arc::new(RwLock::new(None));
+
+    let (save_tx, save_rx): (Sender<FileOptions>, Receiver<FileOptions>) =
+        crossbeam_channel::unbounded();
+    let (load_tx, load_rx): (Sender<PathBuf>, Receiver<PathBuf>) = crossbeam_channel::unbounded();
+    let (loaded_names_tx, loaded_names_rx): (Sender<Vec<String>>, Receiver<Vec<String>>) =
+        crossbeam_channel::unbounded();
+    let (send_tx, send_rx): (Sender<String>, Receiver<String>) = crossbeam_channel::unbounded();
+    let (serial_cmd_tx, serial_cmd_rx): (Sender<SerialCommand>, Receiver<SerialCommand>) =
+        crossbeam_channel::unbounded();
+    let (gui_cmd_tx, gui_cmd_rx): (Sender<GuiCommand>, Receiver<GuiCommand>) =
+        crossbeam_channel::unbounded();
+    let (raw_data_tx, raw_data_rx): (Sender<Packet>, Receiver<Packet>) =
+        crossbeam_channel::unbounded();
+
+    let serial_device_lock = device_lock.clone();
+    let serial_connected_lock = connected_lock.clone();
+    let serial_modem_lock = modem_lock.clone();
+    let serial_transfer_lock = transfer_lock.clone();
+
+    let _serial_thread_handler = thread::spawn(move || {
+        serial_thread(
+            send_rx,
+            serial_cmd_rx,
+            raw_data_tx,
+            serial_device_lock,
+            devices_lock,
+            serial_connected_lock,
+            serial_modem_lock,
+            serial_transfer_lock,
+            t_zero,
+        );
+    });
+
+    let main_data_lock = data_lock.clone();
+    let main_device_lock = device_lock.clone();
+
+    let _main_thread_handler = thread::spawn(move || {
+        main_thread(
+            sync_tx,
+            main_data_lock,
+            raw_data_rx,
+            save_rx,
+            load_rx,
+            loaded_names_tx,
+            gui_cmd_rx,
+            main_device_lock,
+            column_labels,
+        );
+    });
+
+    Connection {
+        device_lock,
+        connected_lock,
+        modem_lock,
+        transfer_lock,
+        data_lock,
+        save_tx,
+        load_tx,
+        load_names_rx: loaded_names_rx,
+        send_tx,
+        serial_cmd_tx,
+        gui_cmd_tx,
+    }
+}
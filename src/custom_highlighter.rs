@@ -4,6 +4,7 @@ use eframe::egui::{FontFamily, FontId};
 
 use regex::Regex;
 use regex::RegexSet;
+use std::ops::Range;
 const DEFAULT_FONT_ID: FontId = FontId::new(14.0, FontFamily::Monospace);
 
 #[derive(Debug, Clone, Copy)]
@@ -21,10 +22,14 @@ impl HighLightElement {
         }
     }
 }
+/// Token index of the `marked` ranges of [`highlight_impl`].
+const MARKED: usize = usize::MAX;
+
 pub fn highlight_impl(
     _ctx: &egui::Context,
     text: &str,
     tokens: Vec<String>,
+    marked: Vec<Range<usize>>,
     default_color: Color32,
 ) -> Option<LayoutJob> {
     // Extremely simple syntax highlighter for when we compile without syntect
@@ -49,16 +54,23 @@ pub fn highlight_impl(
         };
     }
 
-    let mut highlight_list: Vec<HighLightElement> = Vec::<HighLightElement>::new();
+    // marked ranges (e.g. invalid sentences) take precedence over the tokens
+    let mut highlight_list: Vec<HighLightElement> = marked
+        .iter()
+        .map(|range| HighLightElement::new(range.start, range.end, MARKED))
+        .collect();
     match RegexSet::new(my_tokens.clone()) {
         Ok(set) => {
             for idx in set.matches(&content_string).into_iter() {
                 for caps in regexs[idx].captures_iter(&content_string) {
-                    highlight_list.push(HighLightElement::new(
-                        caps.get(0).unwrap().start(),
-                        caps.get(0).unwrap().end(),
-                        idx,
-                    ));
+                    let (start, end) = (caps.get(0).unwrap().start(), caps.get(0).unwrap().end());
+                    if marked
+                        .iter()
+                        .any(|range| start < range.end && range.start < end)
+                    {
+                        continue;
+                    }
+                    highlight_list.push(HighLightElement::new(start, end, idx));
                 }
             }
         }
@@ -70,7 +82,7 @@ pub fn highlight_impl(
     let mut job = LayoutJob::default();
     let mut previous = HighLightElement::new(0, 0, 0);
     for matches in highlight_list {
-        if previous.pos_end >= matches.pos_start {
+        if previous.pos_end > matches.pos_start {
             continue;
         }
         job.append(
@@ -78,7 +90,18 @@ pub fn highlight_impl(
             0.0,
             TextFormat::simple(DEFAULT_FONT_ID, default_color),
         );
-        if matches.token_idx == 0 {
+        if matches.token_idx == MARKED {
+            job.append(
+                &text[matches.pos_start..matches.pos_end],
+                0.0,
+                TextFormat {
+                    font_id: DEFAULT_FONT_ID,
+                    color: Color32::WHITE,
+                    background: Color32::from_rgb(170, 40, 40),
+                    ..Default::default()
+                },
+            );
+        } else if matches.token_idx == 0 {
             job.append(
                 &text[matches.pos_start..matches.pos_end],
                 0.0,
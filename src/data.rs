@@ -1,11 +1,15 @@
+use crate::encoding::TextEncoding;
+use crate::nmea::NmeaStatus;
 use egui_plot::PlotPoint;
 use std::fmt;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
 
 #[derive(Clone, Debug, PartialEq)]
 pub enum SerialDirection {
     Send,
     Receive,
+    /// A BREAK condition sent on the line, recorded as an event without data.
+    Break,
 }
 
 impl fmt::Display for SerialDirection {
@@ -13,6 +17,7 @@ impl fmt::Display for SerialDirection {
         match *self {
             SerialDirection::Send => write!(f, "SEND"),
             SerialDirection::Receive => write!(f, "RECV"),
+            SerialDirection::Break => write!(f, "BRK"),
         }
     }
 }
@@ -24,12 +29,26 @@ pub fn get_epoch_ms() -> u128 {
         .as_millis()
 }
 
+/// Returns the time of `time` relative to `t_zero` and since the epoch, both in ms with
+/// microsecond resolution.
+pub fn timestamps(time: Instant, t_zero: Instant) -> (f64, f64) {
+    let relative = time.saturating_duration_since(t_zero).as_micros() as f64 / 1000.0;
+    let epoch = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .saturating_sub(Instant::now().saturating_duration_since(time));
+    (relative, epoch.as_micros() as f64 / 1000.0)
+}
+
 #[derive(Clone, Debug)]
 pub struct Packet {
     pub relative_time: f64,
     pub absolute_time: f64,
     pub direction: SerialDirection,
     pub payload: String,
+    pub raw: Vec<u8>,
+    /// Encoding `payload` was decoded with, used to show `raw` as text.
+    pub encoding: TextEncoding,
 }
 
 impl Default for Packet {
@@ -39,6 +58,8 @@ impl Default for Packet {
             absolute_time: get_epoch_ms() as f64,
             direction: SerialDirection::Send,
             payload: "".to_string(),
+            raw: vec![],
+            encoding: TextEncoding::default(),
         }
     }
 }
@@ -47,7 +68,7 @@ impl Default for Packet {
 pub struct DataContainer {
     pub time: Vec<f64>,
     pub absolute_time: Vec<f64>,
-    pub dataset: Vec<Vec<f32>>,
+    pub dataset: Vec<Vec<f64>>,
     pub raw_traffic: Vec<Packet>,
     pub loaded_from_file: bool,
 }
@@ -68,4 +89,12 @@ impl Default for DataContainer {
 pub struct GuiOutputDataContainer {
     pub prints: Vec<String>,
     pub plots: Vec<(String, Vec<PlotPoint>)>,
+    /// Received binary frames that do not match the frame schema.
+    pub bad_frames: usize,
+    /// Received lines the robust numbers mode could not parse.
+    pub malformed_lines: usize,
+    /// Received NMEA sentences whose checksum does not match.
+    pub bad_checksums: usize,
+    /// Fix and satellites of the received NMEA sentences.
+    pub nmea: NmeaStatus,
 }
@@ -0,0 +1,633 @@
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::f64::consts::PI;
+use std::fmt;
+
+/// A virtual channel computed from the columns of each sample, e.g. `P = V * I`.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct DerivedChannel {
+    pub name: String,
+    pub expression: String,
+}
+
+/// Reference to a column: `$0` by index, `ax` or `"Column 0"` by name.
+#[derive(Debug, Clone, PartialEq)]
+enum Column {
+    Index(usize),
+    Name(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Function {
+    Abs,
+    Sqrt,
+    Exp,
+    Ln,
+    Log10,
+    Sin,
+    Cos,
+    Tan,
+    Asin,
+    Acos,
+    Atan,
+    Atan2,
+    Min,
+    Max,
+    Hypot,
+}
+
+impl Function {
+    fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "abs" => Function::Abs,
+            "sqrt" => Function::Sqrt,
+            "exp" => Function::Exp,
+            "ln" => Function::Ln,
+            "log10" => Function::Log10,
+            "sin" => Function::Sin,
+            "cos" => Function::Cos,
+            "tan" => Function::Tan,
+            "asin" => Function::Asin,
+            "acos" => Function::Acos,
+            "atan" => Function::Atan,
+            "atan2" => Function::Atan2,
+            "min" => Function::Min,
+            "max" => Function::Max,
+            "hypot" => Function::Hypot,
+            _ => return None,
+        })
+    }
+
+    /// Allowed number of arguments, `None` for any number of at least one.
+    fn arity(&self) -> Option<usize> {
+        match self {
+            Function::Atan2 | Function::Min | Function::Max => Some(2),
+            Function::Hypot => None,
+            _ => Some(1),
+        }
+    }
+
+    fn apply(&self, args: &[f64]) -> f64 {
+        match self {
+            Function::Abs => args[0].abs(),
+            Function::Sqrt => args[0].sqrt(),
+            Function::Exp => args[0].exp(),
+            Function::Ln => args[0].ln(),
+            Function::Log10 => args[0].log10(),
+            Function::Sin => args[0].sin(),
+            Function::Cos => args[0].cos(),
+            Function::Tan => args[0].tan(),
+            Function::Asin => args[0].asin(),
+            Function::Acos => args[0].acos(),
+            Function::Atan => args[0].atan(),
+            Function::Atan2 => args[0].atan2(args[1]),
+            Function::Min => args[0].min(args[1]),
+            Function::Max => args[0].max(args[1]),
+            Function::Hypot => args.iter().map(|arg| arg * arg).sum::<f64>().sqrt(),
+        }
+    }
+}
+
+/// Functions that depend on the previous samples. Samples without a value (NaN) are skipped.
+#[derive(Debug, Clone, PartialEq)]
+enum Filter {
+    /// Mean of the last `len` values.
+    Average { len: usize, window: VecDeque<f64> },
+    /// First order low-pass with the time constant `tau` in s.
+    LowPass { tau: f64, last: Option<(f64, f64)> },
+    /// Change per s.
+    Derivative { last: Option<(f64, f64)> },
+    /// Trapezoidal integral over s.
+    Integral { sum: f64, last: Option<(f64, f64)> },
+}
+
+impl Filter {
+    /// `time` is in s.
+    fn apply(&mut self, value: f64, time: f64) -> f64 {
+        if value.is_nan() {
+            return f64::NAN;
+        }
+        match self {
+            Filter::Average { len, window } => {
+                window.push_back(value);
+                if window.len() > *len {
+                    window.pop_front();
+                }
+                window.iter().sum::<f64>() / window.len() as f64
+            }
+            Filter::LowPass { tau, last } => {
+                let filtered = match *last {
+                    Some((last_time, last_value)) => {
+                        let dt = (time - last_time).max(0.0);
+                        if *tau > 0.0 {
+                            last_value + (value - last_value) * dt / (*tau + dt)
+                        } else {
+                            value
+                        }
+                    }
+                    None => value,
+                };
+                *last = Some((time, filtered));
+                filtered
+            }
+            Filter::Derivative { last } => {
+                let derivative = match *last {
+                    Some((last_time, last_value)) if time > last_time => {
+                        (value - last_value) / (time - last_time)
+                    }
+                    _ => f64::NAN,
+                };
+                *last = Some((time, value));
+                derivative
+            }
+            Filter::Integral { sum, last } => {
+                if let Some((last_time, last_value)) = *last {
+                    *sum += (value + last_value) / 2.0 * (time - last_time);
+                }
+                *last = Some((time, value));
+                *sum
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Node {
+    Number(f64),
+    Column(Column),
+    Negate(Box<Node>),
+    Binary(char, Box<Node>, Box<Node>),
+    Function(Function, Vec<Node>),
+    Filter(Filter, Box<Node>),
+}
+
+impl Node {
+    fn eval(&mut self, time: f64, column: &dyn Fn(&Column) -> f64) -> f64 {
+        match self {
+            Node::Number(value) => *value,
+            Node::Column(reference) => column(reference),
+            Node::Negate(node) => -node.eval(time, column),
+            Node::Binary(op, left, right) => {
+                let left = left.eval(time, column);
+                let right = right.eval(time, column);
+                match op {
+                    '+' => left + right,
+                    '-' => left - right,
+                    '*' => left * right,
+                    '/' => left / right,
+                    '%' => left % right,
+                    _ => left.powf(right),
+                }
+            }
+            Node::Function(function, args) => {
+                let args: Vec<f64> = args.iter_mut().map(|arg| arg.eval(time, column)).collect();
+                function.apply(&args)
+            }
+            Node::Filter(filter, node) => {
+                let value = node.eval(time, column);
+                filter.apply(value, time)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(f64),
+    Ident(String),
+    Column(Column),
+    Op(char),
+    Open,
+    Close,
+    Comma,
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Token::Number(value) => write!(f, "{value}"),
+            Token::Ident(name) => write!(f, "{name}"),
+            Token::Column(Column::Index(index)) => write!(f, "${index}"),
+            Token::Column(Column::Name(name)) => write!(f, "\"{name}\""),
+            Token::Op(op) => write!(f, "{op}"),
+            Token::Open => write!(f, "("),
+            Token::Close => write!(f, ")"),
+            Token::Comma => write!(f, ","),
+        }
+    }
+}
+
+fn tokenize(text: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = vec![];
+    let mut chars = text.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        match c {
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            '0'..='9' | '.' => {
+                let mut number = String::new();
+                while let Some(&c) = chars.peek() {
+                    // exponents like 1e-3
+                    let sign = matches!(c, '+' | '-') && number.ends_with(['e', 'E']);
+                    if c.is_ascii_digit() || matches!(c, '.' | 'e' | 'E') || sign {
+                        number.push(c);
+                        chars.next();
+                    } else {
+                        break;
+                    }
+                }
+                let value = number
+                    .parse()
+                    .map_err(|_| format!("invalid number '{number}'"))?;
+                tokens.push(Token::Number(value));
+            }
+            '$' => {
+                chars.next();
+                let mut index = String::new();
+                while let Some(&c) = chars.peek().filter(|c| c.is_ascii_digit()) {
+                    index.push(c);
+                    chars.next();
+                }
+                let index = index
+                    .parse()
+                    .map_err(|_| "expected a column index after '$'".to_string())?;
+                tokens.push(Token::Column(Column::Index(index)));
+            }
+            '"' => {
+                chars.next();
+                let mut name = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some(c) => name.push(c),
+                        None => return Err("missing closing '\"'".to_string()),
+                    }
+                }
+                tokens.push(Token::Column(Column::Name(name)));
+            }
+            c if c.is_alphabetic() || c == '_' => {
+                let mut ident = String::new();
+                while let Some(&c) = chars.peek() {
+                    if c.is_alphanumeric() || matches!(c, '_' | '.') {
+                        ident.push(c);
+                        chars.next();
+                    } else {
+                        break;
+                    }
+                }
+                tokens.push(Token::Ident(ident));
+            }
+            '+' | '-' | '*' | '/' | '%' | '^' => {
+                tokens.push(Token::Op(c));
+                chars.next();
+            }
+            '(' => {
+                tokens.push(Token::Open);
+                chars.next();
+            }
+            ')' => {
+                tokens.push(Token::Close);
+                chars.next();
+            }
+            ',' => {
+                tokens.push(Token::Comma);
+                chars.next();
+            }
+            c => return Err(format!("unexpected '{c}'")),
+        }
+    }
+    Ok(tokens)
+}
+
+/// Recursive descent parser, `^` binds stronger than the sign, which binds stronger than
+/// `*`, `/` and `%`, which bind stronger than `+` and `-`.
+struct ExpressionParser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl ExpressionParser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn expect(&mut self, expected: Token, text: &str) -> Result<(), String> {
+        if self.next() == Some(expected) {
+            Ok(())
+        } else {
+            Err(format!("expected '{text}'"))
+        }
+    }
+
+    fn sum(&mut self) -> Result<Node, String> {
+        let mut node = self.product()?;
+        while let Some(Token::Op(op @ ('+' | '-'))) = self.peek().cloned() {
+            self.pos += 1;
+            node = Node::Binary(op, Box::new(node), Box::new(self.product()?));
+        }
+        Ok(node)
+    }
+
+    fn product(&mut self) -> Result<Node, String> {
+        let mut node = self.unary()?;
+        while let Some(Token::Op(op @ ('*' | '/' | '%'))) = self.peek().cloned() {
+            self.pos += 1;
+            node = Node::Binary(op, Box::new(node), Box::new(self.unary()?));
+        }
+        Ok(node)
+    }
+
+    fn unary(&mut self) -> Result<Node, String> {
+        match self.peek() {
+            Some(Token::Op('-')) => {
+                self.pos += 1;
+                Ok(Node::Negate(Box::new(self.unary()?)))
+            }
+            Some(Token::Op('+')) => {
+                self.pos += 1;
+                self.unary()
+            }
+            _ => self.power(),
+        }
+    }
+
+    fn power(&mut self) -> Result<Node, String> {
+        let base = self.atom()?;
+        if self.peek() == Some(&Token::Op('^')) {
+            self.pos += 1;
+            // right associative, 2^3^2 is 2^9
+            let exponent = self.unary()?;
+            return Ok(Node::Binary('^', Box::new(base), Box::new(exponent)));
+        }
+        Ok(base)
+    }
+
+    fn atom(&mut self) -> Result<Node, String> {
+        match self.next() {
+            Some(Token::Number(value)) => Ok(Node::Number(value)),
+            Some(Token::Column(column)) => Ok(Node::Column(column)),
+            Some(Token::Open) => {
+                let node = self.sum()?;
+                self.expect(Token::Close, ")")?;
+                Ok(node)
+            }
+            Some(Token::Ident(name)) if self.peek() == Some(&Token::Open) => {
+                self.pos += 1;
+                let mut args = vec![self.sum()?];
+                while self.peek() == Some(&Token::Comma) {
+                    self.pos += 1;
+                    args.push(self.sum()?);
+                }
+                self.expect(Token::Close, ")")?;
+                call(&name, args)
+            }
+            Some(Token::Ident(name)) if name == "pi" => Ok(Node::Number(PI)),
+            Some(Token::Ident(name)) => Ok(Node::Column(Column::Name(name))),
+            Some(token) => Err(format!("unexpected '{token}'")),
+            None => Err("unexpected end".to_string()),
+        }
+    }
+}
+
+/// Builds the node of a function call, filters take their parameter as a number.
+fn call(name: &str, mut args: Vec<Node>) -> Result<Node, String> {
+    let parameter = |args: &[Node], name: &str| match args {
+        [_, Node::Number(parameter)] => Ok(*parameter),
+        _ => Err(format!("{name}(x, n) needs a number as second argument")),
+    };
+    let filter = match name {
+        "avg" => {
+            let len = parameter(&args, name)?;
+            if len < 1.0 {
+                return Err("avg(x, n) needs at least one sample".to_string());
+            }
+            Filter::Average {
+                len: len as usize,
+                window: VecDeque::new(),
+            }
+        }
+        "lowpass" => Filter::LowPass {
+            tau: parameter(&args, name)?,
+            last: None,
+        },
+        "deriv" | "integral" if args.len() != 1 => {
+            return Err(format!("{name}(x) needs one argument"));
+        }
+        "deriv" => Filter::Derivative { last: None },
+        "integral" => Filter::Integral {
+            sum: 0.0,
+            last: None,
+        },
+        _ => {
+            let function =
+                Function::from_name(name).ok_or_else(|| format!("unknown function '{name}'"))?;
+            if function.arity().is_some_and(|arity| arity != args.len()) {
+                return Err(format!(
+                    "{name} needs {} argument(s)",
+                    function.arity().unwrap()
+                ));
+            }
+            return Ok(Node::Function(function, args));
+        }
+    };
+    Ok(Node::Filter(filter, Box::new(args.swap_remove(0))))
+}
+
+/// A parsed expression with the state of its filters.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Expression {
+    root: Node,
+}
+
+impl Expression {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut parser = ExpressionParser {
+            tokens: tokenize(text)?,
+            pos: 0,
+        };
+        let root = parser.sum()?;
+        match parser.peek() {
+            None => Ok(Expression { root }),
+            Some(token) => Err(format!("unexpected '{token}'")),
+        }
+    }
+
+    /// Evaluates the expression for the next sample, `time` is in s and `columns` holds the
+    /// names of the columns of `values`. Unknown columns are NaN.
+    pub fn eval(&mut self, time: f64, columns: &[String], values: &[f64]) -> f64 {
+        let column = |reference: &Column| {
+            let index = match reference {
+                Column::Index(index) => Some(*index),
+                Column::Name(name) => columns.iter().position(|column| column == name),
+            };
+            index
+                .and_then(|index| values.get(index))
+                .copied()
+                .unwrap_or(f64::NAN)
+        };
+        self.root.eval(time, &column)
+    }
+}
+
+/// The derived channels of a connection with a value for every sample.
+#[derive(Debug, Clone, Default)]
+pub struct DerivedChannels {
+    /// The channels as defined, including the invalid ones.
+    definitions: Vec<DerivedChannel>,
+    channels: Vec<DerivedChannel>,
+    expressions: Vec<Expression>,
+    pub dataset: Vec<Vec<f64>>,
+}
+
+impl DerivedChannels {
+    /// Channels with an invalid expression are left out.
+    pub fn new(definitions: &[DerivedChannel]) -> Self {
+        let channels: Vec<DerivedChannel> = definitions
+            .iter()
+            .filter(|channel| match Expression::parse(&channel.expression) {
+                Ok(_) => true,
+                Err(err) => {
+                    log::error!("invalid expression of {}: {err}", channel.name);
+                    false
+                }
+            })
+            .cloned()
+            .collect();
+        let mut derived = DerivedChannels {
+            definitions: definitions.to_vec(),
+            channels,
+            ..DerivedChannels::default()
+        };
+        derived.clear();
+        derived
+    }
+
+    pub fn definitions(&self) -> &[DerivedChannel] {
+        &self.definitions
+    }
+
+    pub fn channels(&self) -> &[DerivedChannel] {
+        &self.channels
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        self.channels
+            .iter()
+            .map(|channel| channel.name.clone())
+            .collect()
+    }
+
+    /// Removes the values and resets the state of the filters.
+    pub fn clear(&mut self) {
+        self.expressions = self
+            .channels
+            .iter()
+            .filter_map(|channel| Expression::parse(&channel.expression).ok())
+            .collect();
+        self.dataset = vec![vec![]; self.channels.len()];
+    }
+
+    /// Appends the values of the next sample, `time` is in ms.
+    pub fn push(&mut self, time: f64, columns: &[String], values: &[f64]) -> Vec<f64> {
+        let derived: Vec<f64> = self
+            .expressions
+            .iter_mut()
+            .map(|expression| expression.eval(time / 1000.0, columns, values))
+            .collect();
+        for (set, value) in self.dataset.iter_mut().zip(&derived) {
+            set.push(*value);
+        }
+        derived
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval(text: &str) -> f64 {
+        Expression::parse(text).unwrap().eval(0.0, &[], &[])
+    }
+
+    /// Evaluates the expression for the samples `(time in s, x)`.
+    fn eval_series(text: &str, samples: &[(f64, f64)]) -> Vec<f64> {
+        let mut expression = Expression::parse(text).unwrap();
+        let columns = vec!["x".to_string()];
+        samples
+            .iter()
+            .map(|(time, x)| expression.eval(*time, &columns, &[*x]))
+            .collect()
+    }
+
+    #[test]
+    fn power_binds_tighter_than_negation() {
+        assert_eq!(eval("-2^2"), -4.0);
+        assert_eq!(eval("(-2)^2"), 4.0);
+    }
+
+    #[test]
+    fn power_is_right_associative() {
+        assert_eq!(eval("2^3^2"), 512.0);
+        assert_eq!(eval("(2^3)^2"), 64.0);
+    }
+
+    #[test]
+    fn precedence() {
+        assert_eq!(eval("1 + 2 * 3"), 7.0);
+        assert_eq!(eval("7 % 4 - 1"), 2.0);
+    }
+
+    #[test]
+    fn wrong_number_of_arguments() {
+        assert!(Expression::parse("sqrt(1, 2)").is_err());
+        assert!(Expression::parse("atan2(1)").is_err());
+        assert!(Expression::parse("deriv(x, 2)").is_err());
+        assert!(Expression::parse("avg(x)").is_err());
+        assert_eq!(eval("hypot(3, 4)"), 5.0);
+    }
+
+    #[test]
+    fn deriv_skips_nan() {
+        let values = eval_series("deriv(x)", &[(0.0, 1.0), (1.0, f64::NAN), (2.0, 5.0)]);
+        assert!(values[0].is_nan());
+        assert!(values[1].is_nan());
+        // the missing sample does not reset the previous value
+        assert_eq!(values[2], 2.0);
+    }
+
+    #[test]
+    fn integral_skips_nan() {
+        let values = eval_series(
+            "integral(x)",
+            &[(0.0, 1.0), (1.0, f64::NAN), (2.0, 1.0), (3.0, 3.0)],
+        );
+        assert_eq!(values[0], 0.0);
+        assert!(values[1].is_nan());
+        assert_eq!(values[2], 2.0);
+        assert_eq!(values[3], 4.0);
+    }
+
+    #[test]
+    fn invalid_channels_are_kept_as_defined() {
+        let definitions = vec![
+            DerivedChannel {
+                name: "P".to_string(),
+                expression: "V * I".to_string(),
+            },
+            DerivedChannel {
+                name: "bad".to_string(),
+                expression: "V *".to_string(),
+            },
+        ];
+        let derived = DerivedChannels::new(&definitions);
+        assert_eq!(derived.definitions(), definitions.as_slice());
+        assert_eq!(derived.names(), vec!["P".to_string()]);
+    }
+}
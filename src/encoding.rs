@@ -0,0 +1,162 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Characters of Windows-1252 in the range 0x80..=0x9F, the rest is equal to Latin-1.
+/// The five unassigned bytes are mapped to the C1 control characters of the same value.
+const WINDOWS_1252_HIGH: [char; 32] = [
+    '\u{20AC}', '\u{0081}', '\u{201A}', '\u{0192}', '\u{201E}', '\u{2026}', '\u{2020}', '\u{2021}',
+    '\u{02C6}', '\u{2030}', '\u{0160}', '\u{2039}', '\u{0152}', '\u{008D}', '\u{017D}', '\u{008F}',
+    '\u{0090}', '\u{2018}', '\u{2019}', '\u{201C}', '\u{201D}', '\u{2022}', '\u{2013}', '\u{2014}',
+    '\u{02DC}', '\u{2122}', '\u{0161}', '\u{203A}', '\u{0153}', '\u{009D}', '\u{017E}', '\u{0178}',
+];
+
+/// Names of the ASCII control characters 0x00..=0x1F.
+const CONTROL_NAMES: [&str; 32] = [
+    "NUL", "SOH", "STX", "ETX", "EOT", "ENQ", "ACK", "BEL", "BS", "HT", "LF", "VT", "FF", "CR",
+    "SO", "SI", "DLE", "DC1", "DC2", "DC3", "DC4", "NAK", "SYN", "ETB", "CAN", "EM", "SUB", "ESC",
+    "FS", "GS", "RS", "US",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub enum TextEncoding {
+    #[default]
+    Utf8,
+    /// Windows-1252, a superset of the printable Latin-1 (ISO 8859-1) characters.
+    Windows1252,
+    /// 7 bit ASCII, other bytes are shown as replacement characters.
+    Ascii,
+}
+
+impl TextEncoding {
+    pub const ALL: [TextEncoding; 3] = [
+        TextEncoding::Utf8,
+        TextEncoding::Windows1252,
+        TextEncoding::Ascii,
+    ];
+
+    /// Decodes received bytes, invalid bytes are replaced by U+FFFD.
+    pub fn decode(&self, bytes: &[u8]) -> String {
+        match self {
+            TextEncoding::Utf8 => String::from_utf8_lossy(bytes).into_owned(),
+            TextEncoding::Windows1252 => bytes
+                .iter()
+                .map(|&b| match b {
+                    0x80..=0x9F => WINDOWS_1252_HIGH[(b - 0x80) as usize],
+                    _ => b as char,
+                })
+                .collect(),
+            TextEncoding::Ascii => bytes
+                .iter()
+                .map(|&b| {
+                    if b.is_ascii() {
+                        b as char
+                    } else {
+                        char::REPLACEMENT_CHARACTER
+                    }
+                })
+                .collect(),
+        }
+    }
+
+    /// Encodes text to send, characters that can not be encoded are sent as `?`.
+    pub fn encode(&self, text: &str) -> Vec<u8> {
+        match self {
+            TextEncoding::Utf8 => text.as_bytes().to_vec(),
+            TextEncoding::Windows1252 => text
+                .chars()
+                .map(|c| match c as u32 {
+                    0x00..=0x7F | 0xA0..=0xFF => c as u8,
+                    _ => WINDOWS_1252_HIGH
+                        .iter()
+                        .position(|&high| high == c)
+                        .map(|i| 0x80 + i as u8)
+                        .unwrap_or(b'?'),
+                })
+                .collect(),
+            TextEncoding::Ascii => text
+                .chars()
+                .map(|c| if c.is_ascii() { c as u8 } else { b'?' })
+                .collect(),
+        }
+    }
+}
+
+impl fmt::Display for TextEncoding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TextEncoding::Utf8 => write!(f, "UTF-8"),
+            TextEncoding::Windows1252 => write!(f, "Latin-1/Windows-1252"),
+            TextEncoding::Ascii => write!(f, "ASCII"),
+        }
+    }
+}
+
+/// How control characters are shown in the console.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum ControlChars {
+    /// Printed as they are (invisible).
+    #[default]
+    AsIs,
+    /// `<CR>`, `<LF>`, `<ESC>`, ...
+    Names,
+    /// `\r`, `\n`, `\t` and `\xHH`.
+    Escapes,
+    /// Unicode control pictures `␍`, `␊`, `␛`, ...
+    Pictures,
+}
+
+impl ControlChars {
+    pub const ALL: [ControlChars; 4] = [
+        ControlChars::AsIs,
+        ControlChars::Names,
+        ControlChars::Escapes,
+        ControlChars::Pictures,
+    ];
+
+    /// Makes the control characters of `text` visible.
+    /// A line feed is still followed by a line break, unless it ends the text.
+    pub fn visualize(&self, text: &str) -> String {
+        if *self == ControlChars::AsIs {
+            return text.to_string();
+        }
+        let mut out = String::with_capacity(text.len());
+        let mut chars = text.chars().peekable();
+        while let Some(c) = chars.next() {
+            if !c.is_control() {
+                out.push(c);
+                continue;
+            }
+            let code = c as u32;
+            match (self, code) {
+                (ControlChars::Names, 0x00..=0x1F) => {
+                    out.push_str(&format!("<{}>", CONTROL_NAMES[code as usize]))
+                }
+                (ControlChars::Names, 0x7F) => out.push_str("<DEL>"),
+                (ControlChars::Escapes, 0x0D) => out.push_str("\\r"),
+                (ControlChars::Escapes, 0x0A) => out.push_str("\\n"),
+                (ControlChars::Escapes, 0x09) => out.push_str("\\t"),
+                (ControlChars::Pictures, 0x00..=0x1F) => {
+                    out.push(char::from_u32(0x2400 + code).unwrap_or(c))
+                }
+                (ControlChars::Pictures, 0x7F) => out.push('\u{2421}'),
+                // C1 control characters have no name or picture
+                _ => out.push_str(&format!("\\x{code:02x}")),
+            }
+            if c == '\n' && chars.peek().is_some() {
+                out.push('\n');
+            }
+        }
+        out
+    }
+}
+
+impl fmt::Display for ControlChars {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ControlChars::AsIs => write!(f, "As is"),
+            ControlChars::Names => write!(f, "Names <CR>"),
+            ControlChars::Escapes => write!(f, "Escapes \\r"),
+            ControlChars::Pictures => write!(f, "Pictures \u{240D}"),
+        }
+    }
+}
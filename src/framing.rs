@@ -0,0 +1,474 @@
+use crate::schema::FrameSchema;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::time::{Duration, Instant};
+
+const SLIP_END: u8 = 0xC0;
+const SLIP_ESC: u8 = 0xDB;
+const SLIP_ESC_END: u8 = 0xDC;
+const SLIP_ESC_ESC: u8 = 0xDD;
+
+/// In auto mode, unterminated data is flushed after this much silence on the line.
+const AUTO_FLUSH_GAP: Duration = Duration::from_millis(100);
+
+/// Data without the end of a frame is flushed as a frame (or dropped if it can not be decoded)
+/// when it reaches this size, so the buffer does not grow without limit.
+const MAX_FRAME_LEN: usize = 4096;
+
+/// Decides how the received byte stream is cut into packets.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
+pub enum Framing {
+    /// Split on `\r\n`, `\r` or `\n`, whatever the device sends.
+    #[default]
+    Auto,
+    /// Split on a fixed byte sequence, written with escapes like the EOL setting (`\r\n`, `\x00`).
+    Delimiter(String),
+    /// Frames with a fixed number of bytes.
+    FixedLength(usize),
+    /// A frame ends when the line is silent for the given number of milliseconds.
+    IdleGap(u64),
+    /// SLIP (RFC 1055) encoded frames.
+    Slip,
+    /// COBS encoded frames, terminated by `0x00`.
+    Cobs,
+    /// Binary frames of a [`FrameSchema`], found by their sync bytes and checksum.
+    Schema(FrameSchema),
+}
+
+impl Framing {
+    pub const ALL: [Framing; 7] = [
+        Framing::Auto,
+        Framing::Delimiter(String::new()),
+        Framing::FixedLength(16),
+        Framing::IdleGap(20),
+        Framing::Slip,
+        Framing::Cobs,
+        Framing::Schema(FrameSchema::new()),
+    ];
+
+    /// Returns true if both framings are the same mode, ignoring the parameters.
+    pub fn same_mode(&self, other: &Framing) -> bool {
+        std::mem::discriminant(self) == std::mem::discriminant(other)
+    }
+}
+
+impl fmt::Display for Framing {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Framing::Auto => write!(f, "Auto"),
+            Framing::Delimiter(_) => write!(f, "Delimiter"),
+            Framing::FixedLength(_) => write!(f, "Fixed Length"),
+            Framing::IdleGap(_) => write!(f, "Idle Gap"),
+            Framing::Slip => write!(f, "SLIP"),
+            Framing::Cobs => write!(f, "COBS"),
+            Framing::Schema(_) => write!(f, "Binary Schema"),
+        }
+    }
+}
+
+/// A complete frame. `raw` holds the bytes as received (decoded for SLIP/COBS),
+/// `payload` the part that is shown as text (without the delimiter, the decoded values
+/// for binary schema frames).
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frame {
+    pub raw: Vec<u8>,
+    pub payload: Vec<u8>,
+    /// Arrival of the last byte of the frame.
+    pub time: Instant,
+}
+
+impl Frame {
+    fn new(raw: Vec<u8>, payload_len: usize, time: Instant) -> Self {
+        let payload = raw[..payload_len].to_vec();
+        Frame { raw, payload, time }
+    }
+
+    fn decoded(raw: Vec<u8>, time: Instant) -> Self {
+        Frame {
+            payload: raw.clone(),
+            raw,
+            time,
+        }
+    }
+}
+
+/// Turns the received byte stream into frames according to a [`Framing`].
+/// Partial frames are kept between reads.
+pub struct Framer {
+    framing: Framing,
+    /// Delimiter or sync bytes.
+    delimiter: Vec<u8>,
+    buffer: Vec<u8>,
+    escaped: bool,
+    /// Binary schema: a frame has been rejected and no valid frame has followed yet.
+    resyncing: bool,
+    /// COBS: the rest of a frame that was too long is dropped up to its end.
+    dropping: bool,
+    last_byte: Instant,
+}
+
+impl Framer {
+    pub fn new(framing: Framing) -> Self {
+        let delimiter = match &framing {
+            Framing::Delimiter(d) => parse_escaped(d),
+            Framing::Schema(schema) => schema.sync_bytes(),
+            _ => vec![],
+        };
+        Framer {
+            framing,
+            delimiter,
+            buffer: vec![],
+            escaped: false,
+            resyncing: false,
+            dropping: false,
+            last_byte: Instant::now(),
+        }
+    }
+
+    /// Feeds received bytes into the framer and returns all frames completed by them.
+    /// `now` is the arrival of the last byte, the arrival of the bytes before it is
+    /// interpolated with `byte_time` (the time it takes to transmit one byte).
+    pub fn push(&mut self, bytes: &[u8], now: Instant, byte_time: Duration) -> Vec<Frame> {
+        if bytes.is_empty() {
+            return vec![];
+        }
+        let last_byte = self.last_byte;
+        // arrival of `bytes[index]`, never before the previous read
+        let arrival = |index: usize| {
+            let later_bytes = bytes.len().saturating_sub(index + 1) as u32;
+            now.checked_sub(byte_time.saturating_mul(later_bytes))
+                .unwrap_or(now)
+                .max(last_byte)
+        };
+        let buffered = self.buffer.len();
+        // index in `bytes` of the last byte of a frame taken from the front of the buffer
+        let mut consumed = 0;
+        let mut frame_end = |len: usize| {
+            consumed += len;
+            arrival((consumed - 1).saturating_sub(buffered))
+        };
+        let mut frames = vec![];
+        match self.framing {
+            Framing::Auto => {
+                self.buffer.extend_from_slice(bytes);
+                while let Some((raw, payload_len)) = self.next_line() {
+                    let time = frame_end(raw.len());
+                    frames.push(Frame::new(raw, payload_len, time));
+                }
+                for raw in self.take_oversized(0) {
+                    let payload_len = strip_line_ending(&raw).len();
+                    frames.push(Frame::new(raw, payload_len, frame_end(MAX_FRAME_LEN)));
+                }
+            }
+            Framing::Delimiter(_) => {
+                self.buffer.extend_from_slice(bytes);
+                if !self.delimiter.is_empty() {
+                    while let Some(pos) = find(&self.buffer, &self.delimiter) {
+                        let end = pos + self.delimiter.len();
+                        let raw = self.buffer.drain(..end).collect();
+                        frames.push(Frame::new(raw, pos, frame_end(end)));
+                    }
+                }
+                // the delimiter is empty or has not arrived, do not buffer forever, but keep
+                // the bytes that might be the start of the delimiter
+                let keep = self.delimiter.len().saturating_sub(1);
+                for raw in self.take_oversized(keep) {
+                    frames.push(Frame::decoded(raw, frame_end(MAX_FRAME_LEN)));
+                }
+            }
+            Framing::FixedLength(length) => {
+                self.buffer.extend_from_slice(bytes);
+                let length = length.max(1);
+                while self.buffer.len() >= length {
+                    let raw = self.buffer.drain(..length).collect();
+                    frames.push(Frame::decoded(raw, frame_end(length)));
+                }
+            }
+            Framing::IdleGap(gap) => {
+                let first_byte = arrival(0);
+                if !self.buffer.is_empty()
+                    && first_byte.duration_since(self.last_byte) >= Duration::from_millis(gap)
+                {
+                    let raw = std::mem::take(&mut self.buffer);
+                    frames.push(Frame::decoded(raw, self.last_byte));
+                }
+                let buffered = self.buffer.len();
+                self.buffer.extend_from_slice(bytes);
+                let mut end = 0;
+                for raw in self.take_oversized(0) {
+                    end += MAX_FRAME_LEN;
+                    frames.push(Frame::decoded(
+                        raw,
+                        arrival((end - 1).saturating_sub(buffered)),
+                    ));
+                }
+            }
+            Framing::Slip => {
+                for (i, &byte) in bytes.iter().enumerate() {
+                    match (self.escaped, byte) {
+                        (false, SLIP_END) => {
+                            if !self.buffer.is_empty() {
+                                let raw = std::mem::take(&mut self.buffer);
+                                frames.push(Frame::decoded(raw, arrival(i)));
+                            }
+                        }
+                        (false, SLIP_ESC) => self.escaped = true,
+                        (false, b) => self.buffer.push(b),
+                        (true, b) => {
+                            self.escaped = false;
+                            self.buffer.push(match b {
+                                SLIP_ESC_END => SLIP_END,
+                                SLIP_ESC_ESC => SLIP_ESC,
+                                // protocol violation, keep the byte as it is
+                                b => b,
+                            });
+                        }
+                    }
+                    if self.buffer.len() >= MAX_FRAME_LEN {
+                        let raw = std::mem::take(&mut self.buffer);
+                        frames.push(Frame::decoded(raw, arrival(i)));
+                    }
+                }
+            }
+            Framing::Cobs => {
+                for (i, &byte) in bytes.iter().enumerate() {
+                    if byte == 0 {
+                        let encoded = std::mem::take(&mut self.buffer);
+                        if std::mem::take(&mut self.dropping) || encoded.is_empty() {
+                            continue;
+                        }
+                        match cobs_decode(&encoded) {
+                            Some(decoded) => frames.push(Frame::decoded(decoded, arrival(i))),
+                            None => log::warn!("dropped invalid COBS frame: {:02x?}", encoded),
+                        }
+                    } else if !self.dropping {
+                        self.buffer.push(byte);
+                        // a part of a frame can not be decoded
+                        if self.buffer.len() >= MAX_FRAME_LEN {
+                            log::warn!("dropped COBS frame longer than {MAX_FRAME_LEN} bytes");
+                            self.buffer.clear();
+                            self.dropping = true;
+                        }
+                    }
+                }
+            }
+            // nothing can be decoded before the first field has been defined
+            Framing::Schema(ref schema) if schema.fields.is_empty() => {}
+            Framing::Schema(ref schema) => {
+                self.buffer.extend_from_slice(bytes);
+                let length = schema.frame_len();
+                // bytes taken from the front of the buffer
+                let mut taken = 0;
+                loop {
+                    // skip everything before the sync bytes
+                    let start = if self.delimiter.is_empty() {
+                        0
+                    } else if let Some(pos) = find(&self.buffer, &self.delimiter) {
+                        pos
+                    } else {
+                        // the end of the buffer might be the start of the sync bytes
+                        let keep = self.delimiter.len() - 1;
+                        let skip = self.buffer.len().saturating_sub(keep);
+                        self.buffer.drain(..skip);
+                        break;
+                    };
+                    self.buffer.drain(..start);
+                    taken += start;
+                    if self.buffer.len() < length {
+                        break;
+                    }
+                    let raw = self.buffer[..length].to_vec();
+                    let time = arrival((taken + length - 1).saturating_sub(buffered));
+                    match schema.decode(&raw) {
+                        Ok(values) => {
+                            let payload = schema.describe(&values).into_bytes();
+                            frames.push(Frame { raw, payload, time });
+                            self.buffer.drain(..length);
+                            taken += length;
+                            self.resyncing = false;
+                        }
+                        Err(err) => {
+                            // the sync bytes might have been data, search the next sync bytes
+                            // after them. Only the first rejected frame of a resync is reported,
+                            // the sync bytes found in its data are no frames of their own.
+                            if !self.resyncing {
+                                let payload = format!("invalid frame: {err}").into_bytes();
+                                frames.push(Frame { raw, payload, time });
+                                self.resyncing = true;
+                            }
+                            let skip = self.delimiter.len().max(1);
+                            self.buffer.drain(..skip);
+                            taken += skip;
+                        }
+                    }
+                }
+            }
+        }
+        self.last_byte = now;
+        frames
+    }
+
+    /// Flushes buffered data that is complete because the line has been silent long enough.
+    pub fn poll(&mut self, now: Instant) -> Vec<Frame> {
+        if self.buffer.is_empty() {
+            return vec![];
+        }
+        let silence = now.duration_since(self.last_byte);
+        match self.framing {
+            Framing::Auto if silence >= AUTO_FLUSH_GAP => {
+                // no line ending received, fall back to splitting on double NUL
+                let buffer = std::mem::take(&mut self.buffer);
+                let mut frames = vec![];
+                let mut rest = buffer.as_slice();
+                while let Some(pos) = find(rest, b"\0\0") {
+                    frames.push(Frame::new(rest[..pos + 2].to_vec(), pos, self.last_byte));
+                    rest = &rest[pos + 2..];
+                }
+                if !rest.is_empty() {
+                    let payload_len = strip_line_ending(rest).len();
+                    frames.push(Frame::new(rest.to_vec(), payload_len, self.last_byte));
+                }
+                frames
+            }
+            Framing::IdleGap(gap) if silence >= Duration::from_millis(gap) => {
+                let raw = std::mem::take(&mut self.buffer);
+                vec![Frame::decoded(raw, self.last_byte)]
+            }
+            _ => vec![],
+        }
+    }
+
+    /// Takes the next line from the buffer, returns it with the length of its payload.
+    /// Takes chunks of `MAX_FRAME_LEN` bytes from the front of the buffer while at least
+    /// `keep` bytes remain behind them.
+    fn take_oversized(&mut self, keep: usize) -> Vec<Vec<u8>> {
+        let mut chunks = vec![];
+        while self.buffer.len() >= MAX_FRAME_LEN + keep {
+            chunks.push(self.buffer.drain(..MAX_FRAME_LEN).collect());
+        }
+        chunks
+    }
+
+    fn next_line(&mut self) -> Option<(Vec<u8>, usize)> {
+        let pos = self
+            .buffer
+            .iter()
+            .position(|b| *b == b'\r' || *b == b'\n')?;
+        let end = if self.buffer[pos] == b'\n' {
+            pos + 1
+        } else if pos + 1 < self.buffer.len() {
+            if self.buffer[pos + 1] == b'\n' {
+                pos + 2
+            } else {
+                pos + 1
+            }
+        } else {
+            // a trailing `\r` might be followed by `\n` in the next read
+            return None;
+        };
+        Some((self.buffer.drain(..end).collect(), pos))
+    }
+}
+
+/// Rates from 0.0 to 1.0 how much the bytes look like valid data for the framing.
+/// SLIP and COBS are rated by the share of bytes in valid frames, everything else by the
+/// share of printable text.
+pub fn plausibility(framing: &Framing, bytes: &[u8]) -> f64 {
+    if bytes.is_empty() {
+        return 0.0;
+    }
+    let valid = match framing {
+        Framing::Slip => bytes
+            .split(|b| *b == SLIP_END)
+            .filter(|frame| {
+                frame.iter().enumerate().all(|(i, b)| {
+                    *b != SLIP_ESC
+                        || matches!(frame.get(i + 1), Some(&SLIP_ESC_END) | Some(&SLIP_ESC_ESC))
+                })
+            })
+            .map(|frame| frame.len() + 1)
+            .sum::<usize>(),
+        Framing::Cobs => bytes
+            .split(|b| *b == 0)
+            .filter(|frame| !frame.is_empty() && cobs_decode(frame).is_some())
+            .map(|frame| frame.len() + 1)
+            .sum::<usize>(),
+        Framing::Schema(schema) => Framer::new(framing.clone())
+            .push(bytes, Instant::now(), Duration::ZERO)
+            .iter()
+            .filter(|frame| schema.decode(&frame.raw).is_ok())
+            .map(|frame| frame.raw.len())
+            .sum::<usize>(),
+        _ => bytes
+            .iter()
+            .filter(|b| b.is_ascii_graphic() || matches!(b, b' ' | b'\r' | b'\n' | b'\t'))
+            .count(),
+    };
+    valid.min(bytes.len()) as f64 / bytes.len() as f64
+}
+
+fn strip_line_ending(bytes: &[u8]) -> &[u8] {
+    let bytes = bytes.strip_suffix(b"\n").unwrap_or(bytes);
+    bytes.strip_suffix(b"\r").unwrap_or(bytes)
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    if needle.is_empty() {
+        return None;
+    }
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+fn cobs_decode(encoded: &[u8]) -> Option<Vec<u8>> {
+    let mut decoded = Vec::with_capacity(encoded.len());
+    let mut i = 0;
+    while i < encoded.len() {
+        let code = encoded[i] as usize;
+        if code == 0 || i + code > encoded.len() {
+            return None;
+        }
+        decoded.extend_from_slice(&encoded[i + 1..i + code]);
+        i += code;
+        if code < 0xFF && i < encoded.len() {
+            decoded.push(0);
+        }
+    }
+    Some(decoded)
+}
+
+/// Parses a string with `\r`, `\n`, `\t`, `\0`, `\\` and `\xHH` escapes into bytes.
+pub fn parse_escaped(s: &str) -> Vec<u8> {
+    let mut bytes = vec![];
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            let mut utf8 = [0; 4];
+            bytes.extend_from_slice(c.encode_utf8(&mut utf8).as_bytes());
+            continue;
+        }
+        match chars.next() {
+            Some('r') => bytes.push(b'\r'),
+            Some('n') => bytes.push(b'\n'),
+            Some('t') => bytes.push(b'\t'),
+            Some('0') => bytes.push(0),
+            Some('\\') => bytes.push(b'\\'),
+            Some('x') => {
+                let hex: String = (0..2).filter_map(|_| chars.next()).collect();
+                match u8::from_str_radix(&hex, 16) {
+                    Ok(b) => bytes.push(b),
+                    Err(_) => {
+                        bytes.extend_from_slice(b"\\x");
+                        bytes.extend_from_slice(hex.as_bytes());
+                    }
+                }
+            }
+            Some(other) => {
+                bytes.push(b'\\');
+                let mut utf8 = [0; 4];
+                bytes.extend_from_slice(other.encode_utf8(&mut utf8).as_bytes());
+            }
+            None => bytes.push(b'\\'),
+        }
+    }
+    bytes
+}
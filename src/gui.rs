@@ -1,17 +1,31 @@
 use core::f32;
-use crossbeam_channel::{Receiver, Sender};
+use crossbeam_channel::Sender;
 use std::cmp::max;
+use std::collections::BTreeMap;
 use std::ops::RangeInclusive;
 use std::path::{Path, PathBuf};
 use std::sync::{Arc, RwLock};
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use crate::color_picker::{color_picker_widget, color_picker_window, COLORS};
+use crate::connection::{start_connection, Connection};
 use crate::custom_highlighter::highlight_impl;
 use crate::data::GuiOutputDataContainer;
-use crate::serial::{clear_serial_settings, save_serial_settings, Device, SerialDevices};
+use crate::derived::{DerivedChannel, Expression};
+use crate::encoding::{ControlChars, TextEncoding};
+use crate::framing::Framing;
+use crate::network::is_network_device;
+use crate::nmea::{invalid_sentences, NmeaStatus};
+use crate::parser::{channel_group, ColumnScale, ParseMode, TimeUnit};
+use crate::schema::{Checksum, Endianness, FieldType, FrameSchema, LengthField, SchemaField};
+use crate::serial::{
+    clear_serial_settings, save_serial_settings, Device, PortInfo, ResetLine, SerialCommand,
+    SerialDevices,
+};
 use crate::settings_window::settings_window;
+use crate::simulation::{Waveform, SIMULATED_REPLAY, SIMULATED_SIGNAL};
 use crate::toggle::toggle;
+use crate::transfer::{FileTransfer, Protocol, TransferState};
 #[cfg(feature = "self_update")]
 use crate::update::check_update;
 use crate::FileOptions;
@@ -24,8 +38,9 @@ use eframe::{egui, Storage};
 use egui::ThemePreference;
 use egui_file_dialog::information_panel::InformationPanel;
 use egui_file_dialog::{FileDialog, Filter};
-use egui_plot::{log_grid_spacer, GridMark, Legend, Line, Plot, PlotPoints};
+use egui_plot::{log_grid_spacer, GridMark, Legend, Line, Plot, PlotPoint, PlotPoints, Points};
 use preferences::Preferences;
+use regex::Regex;
 #[cfg(feature = "self_update")]
 use self_update::update::Release;
 use serde::{Deserialize, Serialize};
@@ -52,6 +67,8 @@ const CLEAR_PLOT_SHORTCUT: KeyboardShortcut =
 #[derive(Clone)]
 pub enum FileDialogState {
     Open,
+    OpenReplay,
+    SendFile,
     Save,
     SavePlot,
     None,
@@ -69,6 +86,10 @@ pub enum GuiCommand {
     Clear,
     ShowTimestamps(bool),
     ShowSentTraffic(bool),
+    ShowHexDump(bool),
+    ShowControlChars(ControlChars),
+    PrettyPrintJson(bool),
+    DerivedChannels(Vec<DerivedChannel>),
 }
 
 #[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
@@ -114,10 +135,61 @@ pub enum ColorWindow {
     ColorIndex(usize),
 }
 
+/// GUI state of a connection, stored here while the tab of another connection is shown.
+#[derive(Clone)]
+struct ConnectionTab {
+    connection: Connection,
+    connected_to_device: bool,
+    command: String,
+    device: String,
+    old_device: String,
+    device_idx: usize,
+    data: GuiOutputDataContainer,
+    file_opened: bool,
+    history: Vec<String>,
+    index: usize,
+    colors: Vec<Color32>,
+    color_vals: Vec<f32>,
+    labels: Vec<String>,
+    show_sent_cmds: bool,
+    show_timestamps: bool,
+    show_hex_dump: bool,
+    control_chars: ControlChars,
+    pretty_print_json: bool,
+    derived_channels: Vec<DerivedChannel>,
+}
+
+impl ConnectionTab {
+    fn new(connection: Connection) -> Self {
+        Self {
+            connection,
+            connected_to_device: false,
+            command: "".to_string(),
+            device: "".to_string(),
+            old_device: "".to_string(),
+            device_idx: 0,
+            data: GuiOutputDataContainer::default(),
+            file_opened: false,
+            history: vec![],
+            index: 0,
+            colors: vec![COLORS[0]],
+            color_vals: vec![0.0],
+            labels: vec!["Column 0".to_string()],
+            show_sent_cmds: true,
+            show_timestamps: true,
+            show_hex_dump: false,
+            control_chars: ControlChars::default(),
+            pretty_print_json: false,
+            derived_channels: vec![],
+        }
+    }
+}
+
 pub struct MyApp {
     connected_to_device: bool,
     command: String,
     device: String,
+    network_address: String,
     old_device: String,
     device_idx: usize,
     serial_devices: SerialDevices,
@@ -134,15 +206,12 @@ pub struct MyApp {
     settings_window_open: bool,
     update_text: String,
     gui_conf: GuiSettingsContainer,
-    device_lock: Arc<RwLock<Device>>,
-    devices_lock: Arc<RwLock<Vec<String>>>,
-    connected_lock: Arc<RwLock<bool>>,
-    data_lock: Arc<RwLock<GuiOutputDataContainer>>,
-    save_tx: Sender<FileOptions>,
-    load_tx: Sender<PathBuf>,
-    load_names_rx: Receiver<Vec<String>>,
-    send_tx: Sender<String>,
-    gui_cmd_tx: Sender<GuiCommand>,
+    connection: Connection,
+    tabs: Vec<ConnectionTab>,
+    active_tab: usize,
+    t_zero: Instant,
+    sync_tx: Sender<bool>,
+    devices_lock: Arc<RwLock<Vec<PortInfo>>>,
     history: Vec<String>,
     index: usize,
     eol: String,
@@ -152,7 +221,15 @@ pub struct MyApp {
     show_color_window: ColorWindow,
     show_sent_cmds: bool,
     show_timestamps: bool,
+    show_hex_dump: bool,
+    control_chars: ControlChars,
+    pretty_print_json: bool,
+    /// Derived channels last sent to the connection.
+    derived_channels: Vec<DerivedChannel>,
     save_raw: bool,
+    transfer_protocol: Protocol,
+    chunk_size: usize,
+    chunk_delay_ms: u64,
     show_warning_window: WindowFeedback,
     do_not_show_clear_warning: bool,
     init: bool,
@@ -165,18 +242,13 @@ pub struct MyApp {
 impl MyApp {
     pub fn new(
         cc: &eframe::CreationContext,
-        data_lock: Arc<RwLock<GuiOutputDataContainer>>,
-        device_lock: Arc<RwLock<Device>>,
-        devices_lock: Arc<RwLock<Vec<String>>>,
+        connection: Connection,
+        devices_lock: Arc<RwLock<Vec<PortInfo>>>,
         devices: SerialDevices,
-        connected_lock: Arc<RwLock<bool>>,
         gui_conf: GuiSettingsContainer,
-        save_tx: Sender<FileOptions>,
-        load_tx: Sender<PathBuf>,
-        load_names_rx: Receiver<Vec<String>>,
-        send_tx: Sender<String>,
-        gui_cmd_tx: Sender<GuiCommand>,
         cli_column_colors: Vec<egui::Color32>,
+        t_zero: Instant,
+        sync_tx: Sender<bool>,
     ) -> Self {
         let mut file_dialog = FileDialog::default()
             //.initial_directory(PathBuf::from("/path/to/app"))
@@ -222,6 +294,7 @@ impl MyApp {
             connected_to_device: false,
             picked_path: PathBuf::new(),
             device: "".to_string(),
+            network_address: "".to_string(),
             old_device: "".to_string(),
             data: GuiOutputDataContainer::default(),
             file_dialog_state: FileDialogState::None,
@@ -236,25 +309,29 @@ impl MyApp {
                         });
                 }
             }),
-            connected_lock,
-            device_lock,
+            tabs: vec![ConnectionTab::new(connection.clone())],
+            active_tab: 0,
+            connection,
+            t_zero,
+            sync_tx,
             devices_lock,
             device_idx: 0,
             serial_devices: devices,
             gui_conf,
-            data_lock,
-            save_tx,
-            load_tx,
-            load_names_rx,
-            send_tx,
-            gui_cmd_tx,
             plotting_range: usize::MAX,
             max_points: 5000,
             plot_serial_display_ratio: 0.45,
             command: "".to_string(),
             show_sent_cmds: true,
             show_timestamps: true,
+            show_hex_dump: false,
+            control_chars: ControlChars::default(),
+            pretty_print_json: false,
+            derived_channels: vec![],
             save_raw: false,
+            transfer_protocol: Protocol::default(),
+            chunk_size: 256,
+            chunk_delay_ms: 0,
             eol: "\\r\\n".to_string(),
             colors: vec![COLORS[0]],
             color_vals: vec![0.0],
@@ -305,8 +382,139 @@ impl MyApp {
         window_feedback
     }
 
+    /// Exchanges the state of the shown connection with the one stored in `tab`.
+    fn swap_tab(&mut self, tab: &mut ConnectionTab) {
+        std::mem::swap(&mut self.connection, &mut tab.connection);
+        std::mem::swap(&mut self.connected_to_device, &mut tab.connected_to_device);
+        std::mem::swap(&mut self.command, &mut tab.command);
+        std::mem::swap(&mut self.device, &mut tab.device);
+        std::mem::swap(&mut self.old_device, &mut tab.old_device);
+        std::mem::swap(&mut self.device_idx, &mut tab.device_idx);
+        std::mem::swap(&mut self.data, &mut tab.data);
+        std::mem::swap(&mut self.file_opened, &mut tab.file_opened);
+        std::mem::swap(&mut self.history, &mut tab.history);
+        std::mem::swap(&mut self.index, &mut tab.index);
+        std::mem::swap(&mut self.colors, &mut tab.colors);
+        std::mem::swap(&mut self.color_vals, &mut tab.color_vals);
+        std::mem::swap(&mut self.labels, &mut tab.labels);
+        std::mem::swap(&mut self.show_sent_cmds, &mut tab.show_sent_cmds);
+        std::mem::swap(&mut self.show_timestamps, &mut tab.show_timestamps);
+        std::mem::swap(&mut self.show_hex_dump, &mut tab.show_hex_dump);
+        std::mem::swap(&mut self.control_chars, &mut tab.control_chars);
+        std::mem::swap(&mut self.pretty_print_json, &mut tab.pretty_print_json);
+        std::mem::swap(&mut self.derived_channels, &mut tab.derived_channels);
+    }
+
+    fn select_tab(&mut self, idx: usize) {
+        if idx == self.active_tab || idx >= self.tabs.len() {
+            return;
+        }
+        let mut tabs = std::mem::take(&mut self.tabs);
+        // store the shown connection and show the selected one
+        self.swap_tab(&mut tabs[self.active_tab]);
+        self.swap_tab(&mut tabs[idx]);
+        // the slot of the shown tab only holds a placeholder
+        tabs[idx] = ConnectionTab::new(self.connection.clone());
+        self.tabs = tabs;
+        self.active_tab = idx;
+        self.show_color_window = ColorWindow::NoShow;
+    }
+
+    fn add_tab(&mut self) {
+        let connection = start_connection(
+            Device::default(),
+            self.t_zero,
+            self.devices_lock.clone(),
+            self.sync_tx.clone(),
+            vec![],
+        );
+        self.tabs.push(ConnectionTab::new(connection));
+        self.select_tab(self.tabs.len() - 1);
+    }
+
+    fn close_tab(&mut self, idx: usize) {
+        if self.tabs.len() <= 1 || idx >= self.tabs.len() {
+            return;
+        }
+        if idx == self.active_tab {
+            self.select_tab(if idx == 0 { 1 } else { idx - 1 });
+        }
+        let tab = self.tabs.remove(idx);
+        if let Ok(mut device) = tab.connection.device_lock.write() {
+            device.name.clear();
+        }
+        if self.active_tab > idx {
+            self.active_tab -= 1;
+        }
+        // dropping the last handles of the connection stops its threads
+    }
+
+    fn draw_connection_tabs(&mut self, ui: &mut egui::Ui) {
+        let mut selected = None;
+        let mut closed = None;
+        ui.horizontal(|ui| {
+            if self.show_warning_window != WindowFeedback::None {
+                ui.disable();
+            }
+            for (idx, tab) in self.tabs.iter().enumerate() {
+                let (device, connected) = if idx == self.active_tab {
+                    (&self.device, self.connected_to_device)
+                } else {
+                    let connected = tab
+                        .connection
+                        .connected_lock
+                        .read()
+                        .map(|connected| *connected)
+                        .unwrap_or(false);
+                    (&tab.device, connected)
+                };
+                let name = if device.is_empty() {
+                    "No device".to_string()
+                } else {
+                    device.replace("/dev/tty.", "")
+                };
+                let color = if connected {
+                    Color32::GREEN
+                } else {
+                    Color32::RED
+                };
+                if ui
+                    .selectable_label(
+                        idx == self.active_tab,
+                        egui::RichText::new(format!("● {name}")).color(color),
+                    )
+                    .clicked()
+                {
+                    selected = Some(idx);
+                }
+                if self.tabs.len() > 1
+                    && ui
+                        .small_button(egui_phosphor::regular::X)
+                        .on_hover_text("Close connection.")
+                        .clicked()
+                {
+                    closed = Some(idx);
+                }
+                ui.separator();
+            }
+            if ui
+                .small_button(egui_phosphor::regular::PLUS)
+                .on_hover_text("Open another connection.")
+                .clicked()
+            {
+                self.add_tab();
+            }
+        });
+        if let Some(idx) = closed {
+            self.close_tab(idx);
+        } else if let Some(idx) = selected {
+            self.select_tab(idx);
+        }
+    }
+
     fn draw_central_panel(&mut self, ui: &mut egui::Ui) {
         egui::CentralPanel::default().show(ui, |ui| {
+            self.draw_connection_tabs(ui);
             let left_border = 10.0;
             // Width
             let width = ui.available_size().x - 2.0 * left_border;
@@ -330,9 +538,33 @@ impl MyApp {
             ui.horizontal(|ui| {
                 ui.add_space(left_border);
                 ui.vertical(|ui| {
-                    if let Ok(gui_data) = self.data_lock.read() {
+                    let derived_channels = self
+                        .serial_devices
+                        .derived_channels
+                        .get(self.device_idx)
+                        .cloned()
+                        .unwrap_or_default();
+                    if derived_channels != self.derived_channels {
+                        if let Err(err) = self
+                            .connection
+                            .gui_cmd_tx
+                            .send(GuiCommand::DerivedChannels(derived_channels.clone()))
+                        {
+                            log::error!("gui_cmd_tx thread send failed: {:?}", err);
+                        }
+                        self.derived_channels = derived_channels;
+                    }
+                    if let Ok(gui_data) = self.connection.data_lock.read() {
+                        // named columns (e.g. key/value parsing) bring their own labels
+                        let names_changed = self.data.plots.len() == gui_data.plots.len()
+                            && self
+                                .data
+                                .plots
+                                .iter()
+                                .zip(&gui_data.plots)
+                                .any(|(old, new)| old.0 != new.0);
                         self.data = gui_data.clone();
-                        if self.data.plots.len() != self.labels.len() {
+                        if names_changed || self.data.plots.len() != self.labels.len() {
                             self.labels = gui_data.plots.iter().map(|d| d.0.clone()).collect();
                         }
                         if self.colors.len() != self.labels.len() {
@@ -351,7 +583,7 @@ impl MyApp {
 
                     // TODO what about self.data.loaded_from_file
                     if self.file_opened {
-                        if let Ok(labels) = self.load_names_rx.try_recv() {
+                        if let Ok(labels) = self.connection.load_names_rx.try_recv() {
                             self.labels = labels;
                             self.colors = (0..max(self.labels.len(), 1))
                                 .map(|i| COLORS[i % COLORS.len()])
@@ -396,7 +628,21 @@ impl MyApp {
                         };
 
                         let t_fmt = |x: GridMark, _range: &RangeInclusive<f64>| {
-                            format!("{:4.2} s", x.value)
+                            // enough decimals to tell the grid lines apart, down to µs
+                            let decimals = (-x.step_size.log10()).ceil().clamp(2.0, 6.0) as usize;
+                            format!("{:4.*} s", decimals, x.value)
+                        };
+
+                        // plots of the channel groups, only used in tag mode
+                        let group_plots = match self.serial_devices.group_plots.get(self.device_idx)
+                        {
+                            Some(group_plots)
+                                if self.serial_devices.devices[self.device_idx].parser.mode
+                                    == ParseMode::Tag =>
+                            {
+                                group_plots.clone()
+                            }
+                            _ => BTreeMap::new(),
                         };
 
                         let plots_ui = ui.vertical(|ui| {
@@ -417,23 +663,41 @@ impl MyApp {
                                 let n = (self.data.prints.len() / self.max_points).max(1);
 
                                 let plot_inner = signal_plot.show(ui, |signal_plot_ui| {
-                                    for (i, (_label, graph)) in self.data.plots.iter().enumerate() {
+                                    for (i, (name, graph)) in self.data.plots.iter().enumerate() {
+                                        if group_plots
+                                            .get(channel_group(name))
+                                            .is_some_and(|plot| *plot != graph_idx)
+                                        {
+                                            continue;
+                                        }
                                         // this check needs to be here for when we change devices (not very elegant)
                                         if i < self.labels.len() {
-                                            signal_plot_ui.line(
-                                                Line::new(
-                                                    self.labels[i].to_string(),
-                                                    PlotPoints::Owned(
-                                                        graph
-                                                            .iter()
-                                                            .skip(window)
-                                                            .step_by(n)
-                                                            .cloned()
-                                                            .collect(),
-                                                    ),
-                                                )
-                                                .color(self.colors[i]),
-                                            );
+                                            let points: Vec<PlotPoint> = graph
+                                                .iter()
+                                                .skip(window)
+                                                .step_by(n)
+                                                .cloned()
+                                                .collect();
+                                            // missing values (NaN) leave gaps in the line
+                                            for segment in points
+                                                .split(|point| point.y.is_nan())
+                                                .filter(|segment| !segment.is_empty())
+                                            {
+                                                let name = self.unit_label(i);
+                                                let series = PlotPoints::Owned(segment.to_vec());
+                                                if segment.len() == 1 {
+                                                    signal_plot_ui.points(
+                                                        Points::new(name, series)
+                                                            .radius(1.5)
+                                                            .color(self.colors[i]),
+                                                    );
+                                                } else {
+                                                    signal_plot_ui.line(
+                                                        Line::new(name, series)
+                                                            .color(self.colors[i]),
+                                                    );
+                                                }
+                                            }
                                         }
                                     }
                                 });
@@ -476,6 +740,9 @@ impl MyApp {
                     } else {
                         Color32::BLACK
                     };
+                    // sentences with a wrong checksum are highlighted
+                    let nmea =
+                        self.serial_devices.devices[self.device_idx].parser.mode == ParseMode::Nmea;
 
                     egui::ScrollArea::vertical()
                         .id_salt("serial_output")
@@ -500,11 +767,17 @@ impl MyApp {
                             let mut layouter =
                                 |ui: &egui::Ui, text: &dyn egui::TextBuffer, wrap_width: f32| {
                                     let string = text.as_str();
+                                    let marked = if nmea {
+                                        invalid_sentences(string)
+                                    } else {
+                                        vec![]
+                                    };
                                     let mut layout_job = highlight_impl(
                                         ui.ctx(),
                                         string,
                                         self.serial_devices.highlight_labels[self.device_idx]
                                             .clone(),
+                                        marked,
                                         Color32::from_rgb(155, 164, 167),
                                     )
                                     .unwrap();
@@ -535,7 +808,9 @@ impl MyApp {
                             self.history.push(self.command.clone());
                             self.index = self.history.len() - 1;
                             let eol = self.eol.replace("\\r", "\r").replace("\\n", "\n");
-                            if let Err(err) = self.send_tx.send(self.command.clone() + &eol) {
+                            if let Err(err) =
+                                self.connection.send_tx.send(self.command.clone() + &eol)
+                            {
                                 log::error!("send_tx thread send failed: {:?}", err);
                             }
                             // stay in focus!
@@ -567,28 +842,79 @@ impl MyApp {
             self.paint_connection_indicator(ui);
         });
 
-        let devices: Vec<String> = if let Ok(read_guard) = self.devices_lock.read() {
+        let mut devices: Vec<PortInfo> = if let Ok(read_guard) = self.devices_lock.read() {
             read_guard.clone()
         } else {
             vec![]
         };
+        // network devices are not enumerated, offer the ones that have been used before
+        for name in self
+            .serial_devices
+            .devices
+            .iter()
+            .map(|dev| &dev.name)
+            .chain([&self.device])
+        {
+            if is_network_device(name) && !devices.iter().any(|port| port.name == *name) {
+                devices.push(PortInfo {
+                    name: name.clone(),
+                    usb: None,
+                });
+            }
+        }
 
-        if !devices.contains(&self.device) {
+        if !devices.iter().any(|port| port.name == self.device) {
             self.device.clear();
         }
-        if let Ok(dev) = self.device_lock.read() {
+        if let Ok(dev) = self.connection.device_lock.read() {
             if !dev.name.is_empty() {
                 self.device = dev.name.clone();
             }
+            let settings = &mut self.serial_devices.devices[self.device_idx];
+            // the device has been reconnected under another port name
+            if let (Some(usb), Some(dev_usb)) = (&settings.usb, &dev.usb) {
+                if !dev.name.is_empty() && settings.name != dev.name && usb.same_device(dev_usb) {
+                    settings.name = dev.name.clone();
+                }
+            }
+            // show the baud rate found by the auto-baud detection
+            if self.connected_to_device && dev.auto_baud && settings.name == dev.name {
+                settings.baud_rate = dev.baud_rate;
+            }
         }
         ui.add_space(10.0);
+        let old_name = self.device.clone();
+        ui.horizontal(|ui| {
+            if self.connected_to_device || self.file_opened {
+                ui.disable();
+            }
+            ui.label("Network");
+            ui.add(
+                egui::TextEdit::singleline(&mut self.network_address)
+                    .hint_text("tcp://host:port")
+                    .desired_width(RIGHT_PANEL_WIDTH * 0.92 - 125.0),
+            )
+            .on_hover_text(
+                "Serial port server (e.g. ser2net), use tcp://host:port for raw TCP or rfc2217://host:port for RFC 2217.",
+            );
+            if ui
+                .add_enabled(
+                    is_network_device(self.network_address.trim()),
+                    egui::Button::new("Add"),
+                )
+                .clicked()
+            {
+                self.device = self.network_address.trim().to_string();
+            }
+        });
+        ui.add_space(5.0);
         ui.horizontal(|ui| {
             ui.label("Device");
             ui.add_space(130.0);
             ui.label("Baud");
         });
 
-        let old_name = self.device.clone();
+        let old_settings = self.serial_devices.devices[self.device_idx].clone();
         ui.horizontal(|ui| {
             if self.file_opened {
                 ui.disable();
@@ -602,15 +928,33 @@ impl MyApp {
                     .selected_text(dev_text)
                     .width(RIGHT_PANEL_WIDTH * 0.92 - 155.0)
                     .show_ui(ui, |ui| {
+                        // the USB details would not fit into the width of the combo box
+                        ui.style_mut().wrap_mode = Some(egui::TextWrapMode::Extend);
                         devices
-                            .into_iter()
+                            .iter()
                             // on macOS each device appears as /dev/tty.* and /dev/cu.*
                             // we only display the /dev/tty.* here
-                            .filter(|dev| !dev.contains("/dev/cu."))
+                            .filter(|dev| !dev.name.contains("/dev/cu."))
                             .for_each(|dev| {
                                 // this makes the names shorter in the UI on UNIX and UNIX-like platforms
-                                let dev_text = dev.replace("/dev/tty.", "");
-                                ui.selectable_value(&mut self.device, dev, dev_text);
+                                let dev_text = dev.name.replace("/dev/tty.", "");
+                                match &dev.usb {
+                                    Some(usb) => {
+                                        ui.selectable_value(
+                                            &mut self.device,
+                                            dev.name.clone(),
+                                            format!("{}  {}", dev_text, usb.summary()),
+                                        )
+                                        .on_hover_text(usb.to_string());
+                                    }
+                                    None => {
+                                        ui.selectable_value(
+                                            &mut self.device,
+                                            dev.name.clone(),
+                                            dev_text,
+                                        );
+                                    }
+                                }
                             });
                     })
                     .response;
@@ -632,11 +976,25 @@ impl MyApp {
                 }
                 WindowFeedback::Clear => {
                     // new device selected, check in previously used devices
+                    let usb = devices
+                        .iter()
+                        .find(|port| port.name == self.device)
+                        .and_then(|port| port.usb.clone());
                     let mut device_is_already_saved = false;
-                    for (idx, dev) in self.serial_devices.devices.iter().enumerate() {
-                        if dev.name == self.device {
+                    for (idx, dev) in self.serial_devices.devices.iter_mut().enumerate() {
+                        // recognize the device by its USB identity, even under another port name
+                        let is_device = match (&dev.usb, &usb) {
+                            (Some(dev_usb), Some(usb)) if dev_usb.serial_number.is_some() => {
+                                dev_usb.same_device(usb)
+                            }
+                            _ => dev.name == self.device,
+                        };
+                        if is_device && !device_is_already_saved {
                             // this is the device!
-                            self.device = dev.name.clone();
+                            dev.name = self.device.clone();
+                            if usb.is_some() {
+                                dev.usb = usb.clone();
+                            }
                             self.device_idx = idx;
                             self.init = true;
                             device_is_already_saved = true;
@@ -646,7 +1004,13 @@ impl MyApp {
                         // create new device in the archive
                         let mut device = Device::default();
                         device.name = self.device.clone();
+                        device.usb = usb;
                         self.serial_devices.devices.push(device);
+                        let devices = self.serial_devices.devices.len();
+                        self.serial_devices
+                            .group_plots
+                            .resize(devices, BTreeMap::new());
+                        self.serial_devices.derived_channels.resize(devices, vec![]);
                         self.serial_devices.number_of_plots.push(1);
                         self.serial_devices.number_of_highlights.push(1);
                         self.serial_devices
@@ -658,7 +1022,8 @@ impl MyApp {
                         self.device_idx = self.serial_devices.devices.len() - 1;
                         save_serial_settings(&self.serial_devices);
                     }
-                    self.gui_cmd_tx
+                    self.connection
+                        .gui_cmd_tx
                         .send(GuiCommand::Clear)
                         .expect("failed to send clear after choosing new device");
                     // need to clear the data here such that we don't get errors in the gui (plot)
@@ -670,22 +1035,44 @@ impl MyApp {
                     self.show_warning_window = WindowFeedback::None;
                 }
             }
+            let settings = &mut self.serial_devices.devices[self.device_idx];
+            let baud_text = match (settings.auto_baud, self.connected_to_device) {
+                (true, true) => format!("Auto {}", settings.baud_rate),
+                (true, false) => "Auto".to_string(),
+                (false, _) => settings.baud_rate.to_string(),
+            };
             egui::ComboBox::from_id_salt("Baud Rate")
-                .selected_text(format!(
-                    "{}",
-                    self.serial_devices.devices[self.device_idx].baud_rate
-                ))
+                .selected_text(baud_text)
                 .width(80.0)
                 .show_ui(ui, |ui| {
-                    if self.connected_to_device {
-                        ui.disable();
-                    }
-                    BAUD_RATES.iter().for_each(|baud_rate| {
-                        ui.selectable_value(
-                            &mut self.serial_devices.devices[self.device_idx].baud_rate,
-                            *baud_rate,
-                            baud_rate.to_string(),
+                    ui.selectable_value(&mut settings.auto_baud, true, "Auto")
+                        .on_hover_text(
+                            "Tries the common baud rates until the received data looks valid.",
                         );
+                    BAUD_RATES.iter().for_each(|baud_rate| {
+                        if ui
+                            .selectable_label(
+                                !settings.auto_baud && settings.baud_rate == *baud_rate,
+                                baud_rate.to_string(),
+                            )
+                            .clicked()
+                        {
+                            settings.baud_rate = *baud_rate;
+                            settings.auto_baud = false;
+                        }
+                    });
+                    ui.separator();
+                    ui.horizontal(|ui| {
+                        ui.label("Custom");
+                        if ui
+                            .add(
+                                egui::DragValue::new(&mut settings.baud_rate)
+                                    .range(50..=20_000_000),
+                            )
+                            .changed()
+                        {
+                            settings.auto_baud = false;
+                        }
                     });
                 });
             let connect_text = if self.connected_to_device {
@@ -694,12 +1081,11 @@ impl MyApp {
                 "Connect"
             };
             if ui.button(connect_text).clicked() {
-                if let Ok(mut device) = self.device_lock.write() {
+                if let Ok(mut device) = self.connection.device_lock.write() {
                     if self.connected_to_device {
                         device.name.clear();
                     } else {
-                        device.name = self.serial_devices.devices[self.device_idx].name.clone();
-                        device.baud_rate = self.serial_devices.devices[self.device_idx].baud_rate;
+                        *device = self.serial_devices.devices[self.device_idx].clone();
                     }
                 }
             }
@@ -715,9 +1101,6 @@ impl MyApp {
             ui.label("Timeout");
         });
         ui.horizontal(|ui| {
-            if self.connected_to_device {
-                ui.disable();
-            }
             egui::ComboBox::from_id_salt("Data Bits")
                 .selected_text(
                     self.serial_devices.devices[self.device_idx]
@@ -826,7 +1209,7 @@ impl MyApp {
                     ui.selectable_value(
                         &mut self.serial_devices.devices[self.device_idx].timeout,
                         Duration::from_millis(0),
-                        "0",
+                        "auto",
                     );
                     ui.selectable_value(
                         &mut self.serial_devices.devices[self.device_idx].timeout,
@@ -846,6 +1229,353 @@ impl MyApp {
                 });
         });
         ui.add_space(5.0);
+        ui.horizontal(|ui| {
+            ui.label("Framing");
+            let framing = &mut self.serial_devices.devices[self.device_idx].framing;
+            egui::ComboBox::from_id_salt("Framing")
+                .selected_text(framing.to_string())
+                .width(100.0)
+                .show_ui(ui, |ui| {
+                    for mode in Framing::ALL {
+                        let selected = framing.same_mode(&mode);
+                        if ui.selectable_label(selected, mode.to_string()).clicked() && !selected {
+                            *framing = mode;
+                        }
+                    }
+                });
+            match framing {
+                Framing::Delimiter(delimiter) => {
+                    ui.add(egui::TextEdit::singleline(delimiter).desired_width(80.0))
+                        .on_hover_text(
+                            "Frame delimiter, use \\r, \\n, \\0 or \\xHH for special bytes.",
+                        );
+                }
+                Framing::FixedLength(length) => {
+                    ui.add(egui::DragValue::new(length).range(1..=4096).suffix(" B"))
+                        .on_hover_text("Number of bytes per frame.");
+                }
+                Framing::IdleGap(gap) => {
+                    ui.add(egui::DragValue::new(gap).range(1..=10_000).suffix(" ms"))
+                        .on_hover_text("A frame ends when the line is silent for this long.");
+                }
+                Framing::Auto | Framing::Slip | Framing::Cobs | Framing::Schema(_) => {}
+            }
+        });
+        let bad_frames = self.data.bad_frames;
+        if let Framing::Schema(schema) = &mut self.serial_devices.devices[self.device_idx].framing {
+            draw_frame_schema(ui, schema, bad_frames);
+        }
+        ui.add_space(5.0);
+        ui.horizontal(|ui| {
+            ui.label("Encoding");
+            let encoding = &mut self.serial_devices.devices[self.device_idx].encoding;
+            egui::ComboBox::from_id_salt("Encoding")
+                .selected_text(encoding.to_string())
+                .width(150.0)
+                .show_ui(ui, |ui| {
+                    for option in TextEncoding::ALL {
+                        ui.selectable_value(encoding, option, option.to_string());
+                    }
+                });
+        });
+        ui.add_space(5.0);
+        let malformed_lines = self.data.malformed_lines;
+        let bad_checksums = self.data.bad_checksums;
+        ui.horizontal(|ui| {
+            ui.label("Parser");
+            let parser = &mut self.serial_devices.devices[self.device_idx].parser;
+            egui::ComboBox::from_id_salt("Parse Mode")
+                .selected_text(parser.mode.to_string())
+                .width(100.0)
+                .show_ui(ui, |ui| {
+                    for mode in ParseMode::ALL {
+                        let text = mode.to_string();
+                        ui.selectable_value(&mut parser.mode, mode, text);
+                    }
+                })
+                .response
+                .on_hover_text(
+                    "Numbers: all numbers of a line by position.\nKey=Value: name=value or name:value pairs, columns are named after them.\nRegex: named capture groups, columns are named after the groups.\nJSON: one object per line, nested numeric fields are named like imu.ax.\nTag: the first field selects a channel group, IMU,0.1,0.2 gives the channels IMU.0 and IMU.1.\nNMEA: GGA, RMC, VTG, GSA and GSV sentences of GNSS receivers, sentences with a wrong checksum are dropped.",
+                );
+            if parser.mode == ParseMode::Numbers {
+                ui.add(toggle(&mut parser.robust)).on_hover_text(
+                    "Keep the series when the number of values changes: new columns are added, missing values leave gaps and lines that are no numbers are skipped instead of resetting the data.",
+                );
+                ui.label("Robust");
+                if parser.robust && malformed_lines > 0 {
+                    ui.label(
+                        egui::RichText::new(format!("{malformed_lines} malformed"))
+                            .color(Color32::RED),
+                    )
+                    .on_hover_text("Received lines with fields that are no numbers.");
+                }
+            }
+            if parser.mode == ParseMode::Nmea && bad_checksums > 0 {
+                ui.label(
+                    egui::RichText::new(format!("{bad_checksums} bad checksums"))
+                        .color(Color32::RED),
+                )
+                .on_hover_text("Received sentences whose checksum does not match.");
+            }
+            if parser.mode == ParseMode::Regex
+                && ui
+                    .button(egui_phosphor::regular::PLUS)
+                    .on_hover_text("Add a regex.")
+                    .clicked()
+            {
+                parser.patterns.push(String::new());
+            }
+        });
+        let parser = &mut self.serial_devices.devices[self.device_idx].parser;
+        if parser.mode == ParseMode::Regex {
+            let mut remove = None;
+            for (i, pattern) in parser.patterns.iter_mut().enumerate() {
+                ui.horizontal(|ui| {
+                    let error = Regex::new(pattern).err();
+                    let text_color = if error.is_some() {
+                        Some(Color32::RED)
+                    } else {
+                        None
+                    };
+                    let response = ui.add(
+                        egui::TextEdit::singleline(pattern)
+                            .hint_text("ax=(?P<ax>\\S+)")
+                            .text_color_opt(text_color)
+                            .desired_width(RIGHT_PANEL_WIDTH * 0.92 - 40.0),
+                    );
+                    match error {
+                        Some(err) => response.on_hover_text(err.to_string()),
+                        None => response.on_hover_text(
+                            "Named capture groups become channels, lines that do not match are only shown in the console.",
+                        ),
+                    };
+                    if ui.button(egui_phosphor::regular::X).clicked() {
+                        remove = Some(i);
+                    }
+                });
+            }
+            if let Some(i) = remove {
+                parser.patterns.remove(i);
+            }
+        }
+        if matches!(parser.mode, ParseMode::Numbers | ParseMode::Tag) {
+            ui.horizontal(|ui| {
+                ui.add(toggle(&mut parser.number_format.radix_literals))
+                    .on_hover_text("Also read integers like 0x1F3A, 0b1011 and 0o17.");
+                ui.label("Hex/Bin/Oct");
+                ui.add(toggle(&mut parser.number_format.decimal_comma))
+                    .on_hover_text(
+                        "Read 3,14 as 3.14, the fields are then separated by ; : or tabs.",
+                    );
+                ui.label("Decimal comma");
+            });
+        }
+        ui.horizontal(|ui| {
+            ui.label("Time column");
+            ui.add(
+                egui::TextEdit::singleline(&mut parser.x_field)
+                    .hint_text("time of arrival")
+                    .desired_width(100.0),
+            )
+            .on_hover_text(
+                "Column with the device time, used instead of the time of arrival: its name (e.g. t) or its index in numbers mode (0 for the first). Lines without it are not plotted.",
+            );
+            if !parser.x_field.is_empty() {
+                egui::ComboBox::from_id_salt("Time Unit")
+                    .selected_text(parser.x_unit.to_string())
+                    .width(50.0)
+                    .show_ui(ui, |ui| {
+                        for unit in TimeUnit::ALL {
+                            let selected = parser.x_unit.same_unit(&unit);
+                            if ui.selectable_label(selected, unit.to_string()).clicked()
+                                && !selected
+                            {
+                                parser.x_unit = unit;
+                            }
+                        }
+                    });
+                if let TimeUnit::Ticks(frequency) = &mut parser.x_unit {
+                    ui.add(
+                        egui::DragValue::new(frequency)
+                            .range(1.0..=1e9)
+                            .suffix(" Hz"),
+                    )
+                    .on_hover_text("Tick frequency of the counter.");
+                }
+            }
+        });
+        if !parser.x_field.is_empty() {
+            ui.horizontal(|ui| {
+                ui.add(toggle(&mut parser.x_wrap)).on_hover_text(
+                    "The device time is a 32 bit counter, count on after it wraps around to 0.",
+                );
+                ui.label("32 bit wrap-around");
+            });
+        }
+        if self.device == SIMULATED_SIGNAL {
+            ui.add_space(5.0);
+            ui.horizontal(|ui| {
+                ui.label("Signal");
+                let simulation = &mut self.serial_devices.devices[self.device_idx].simulation;
+                egui::ComboBox::from_id_salt("Waveform")
+                    .selected_text(simulation.waveform.to_string())
+                    .width(70.0)
+                    .show_ui(ui, |ui| {
+                        for waveform in Waveform::ALL {
+                            ui.selectable_value(
+                                &mut simulation.waveform,
+                                waveform,
+                                waveform.to_string(),
+                            );
+                        }
+                    });
+                ui.add(
+                    egui::DragValue::new(&mut simulation.columns)
+                        .range(1..=32)
+                        .suffix(" columns"),
+                );
+                ui.add(
+                    egui::DragValue::new(&mut simulation.rate)
+                        .range(0.1..=1000.0)
+                        .speed(0.5)
+                        .suffix(" Hz"),
+                )
+                .on_hover_text("Lines per second.");
+            });
+        } else if self.device == SIMULATED_REPLAY {
+            ui.add_space(5.0);
+            ui.horizontal(|ui| {
+                if self.connected_to_device {
+                    ui.disable();
+                }
+                ui.label("Replay");
+                let replay_file = &self.serial_devices.devices[self.device_idx]
+                    .simulation
+                    .replay_file;
+                let file_name = replay_file
+                    .file_name()
+                    .map(|name| name.to_string_lossy().into_owned())
+                    .unwrap_or_else(|| "no file selected".to_string());
+                ui.label(file_name)
+                    .on_hover_text(replay_file.display().to_string());
+                if ui
+                    .button(egui_phosphor::regular::FOLDER_OPEN)
+                    .on_hover_text("Select a raw traffic .csv to replay.")
+                    .clicked()
+                {
+                    self.file_dialog_state = FileDialogState::OpenReplay;
+                    self.file_dialog.pick_file();
+                }
+            });
+        }
+        ui.add_space(5.0);
+        ui.horizontal(|ui| {
+            if self.file_opened {
+                ui.disable();
+            }
+            let device = &mut self.serial_devices.devices[self.device_idx];
+            ui.label("DTR");
+            ui.add(toggle(&mut device.dtr))
+                .on_hover_text("Data Terminal Ready, this level is also set on connect.");
+            ui.label("RTS");
+            ui.add_enabled(
+                device.flow_control != FlowControl::Hardware,
+                toggle(&mut device.rts),
+            )
+            .on_hover_text("Request To Send, this level is also set on connect.");
+            ui.separator();
+            // with hardware flow control RTS can not be pulsed
+            let rts_only = device.flow_control == FlowControl::Hardware
+                && device.reset_pulse.line == ResetLine::Rts;
+            if ui
+                .add_enabled(
+                    self.connected_to_device && !rts_only,
+                    egui::Button::new("Reset"),
+                )
+                .on_hover_text(
+                    "Inverts the reset line(s) for the pulse length, RTS is left to the hardware flow control.",
+                )
+                .clicked()
+            {
+                if let Err(err) = self
+                    .connection
+                    .serial_cmd_tx
+                    .send(SerialCommand::ResetPulse)
+                {
+                    log::error!("serial_cmd_tx thread send failed: {:?}", err);
+                }
+            }
+            egui::ComboBox::from_id_salt("Reset Line")
+                .selected_text(device.reset_pulse.line.to_string())
+                .width(70.0)
+                .show_ui(ui, |ui| {
+                    for line in ResetLine::ALL {
+                        ui.selectable_value(&mut device.reset_pulse.line, line, line.to_string());
+                    }
+                });
+            let mut pulse_ms = device.reset_pulse.duration.as_millis() as u64;
+            if ui
+                .add(
+                    egui::DragValue::new(&mut pulse_ms)
+                        .range(1..=5000)
+                        .suffix(" ms"),
+                )
+                .on_hover_text("Length of the reset pulse.")
+                .changed()
+            {
+                device.reset_pulse.duration = Duration::from_millis(pulse_ms);
+            }
+        });
+        ui.horizontal(|ui| {
+            let modem_lines = self
+                .connection
+                .modem_lock
+                .read()
+                .map(|modem_lines| *modem_lines)
+                .unwrap_or_default();
+            for (name, level, description) in [
+                ("CTS", modem_lines.cts, "Clear To Send"),
+                ("DSR", modem_lines.dsr, "Data Set Ready"),
+                ("DCD", modem_lines.dcd, "Data Carrier Detect"),
+                ("RI", modem_lines.ri, "Ring Indicator"),
+            ] {
+                paint_led(ui, level);
+                ui.label(name).on_hover_text(description);
+                ui.add_space(5.0);
+            }
+            ui.separator();
+            if self.file_opened {
+                ui.disable();
+            }
+            let break_duration = &mut self.serial_devices.devices[self.device_idx].break_duration;
+            if ui
+                .add_enabled(self.connected_to_device, egui::Button::new("Break"))
+                .on_hover_text("Holds the line in the BREAK condition.")
+                .clicked()
+            {
+                if let Err(err) = self
+                    .connection
+                    .serial_cmd_tx
+                    .send(SerialCommand::Break(*break_duration))
+                {
+                    log::error!("serial_cmd_tx thread send failed: {:?}", err);
+                }
+            }
+            let mut break_ms = break_duration.as_millis() as u64;
+            if ui
+                .add(
+                    egui::DragValue::new(&mut break_ms)
+                        .range(1..=10_000)
+                        .suffix(" ms"),
+                )
+                .on_hover_text("Length of the BREAK.")
+                .changed()
+            {
+                *break_duration = Duration::from_millis(break_ms);
+            }
+        });
+        self.draw_file_transfer(ui);
+        ui.add_space(5.0);
         ui.horizontal(|ui| {
             if self.connected_to_device {
                 ui.disable();
@@ -870,10 +1600,26 @@ impl MyApp {
                     .clicked()
             {
                 self.file_opened = false;
-                let _ = self.load_tx.send(PathBuf::new());
+                let _ = self.connection.load_tx.send(PathBuf::new());
                 self.file_dialog_state = FileDialogState::None;
             }
         });
+
+        self.apply_device_settings(&old_settings);
+    }
+
+    /// Applies the changed settings of the selected device to the live connection.
+    fn apply_device_settings(&self, old_settings: &Device) {
+        if self.connected_to_device
+            && old_settings.name == self.serial_devices.devices[self.device_idx].name
+            && *old_settings != self.serial_devices.devices[self.device_idx]
+        {
+            if let Ok(mut device) = self.connection.device_lock.write() {
+                if device.name == old_settings.name {
+                    *device = self.serial_devices.devices[self.device_idx].clone();
+                }
+            }
+        }
     }
     fn draw_export_settings(&mut self, ui: &mut Ui) {
         egui::Grid::new("export_settings")
@@ -955,7 +1701,7 @@ impl MyApp {
             || ui.input_mut(|i| i.consume_shortcut(&CLEAR_PLOT_SHORTCUT))
         {
             log::info!("Cleared recorded Data");
-            if let Err(err) = self.gui_cmd_tx.send(GuiCommand::Clear) {
+            if let Err(err) = self.connection.gui_cmd_tx.send(GuiCommand::Clear) {
                 log::error!("clear_tx thread send failed: {:?}", err);
             }
             // need to clear the data here in order to prevent errors in the gui (plot)
@@ -982,6 +1728,7 @@ impl MyApp {
                 .changed()
             {
                 if let Err(err) = self
+                    .connection
                     .gui_cmd_tx
                     .send(GuiCommand::ShowSentTraffic(self.show_sent_cmds))
                 {
@@ -998,6 +1745,7 @@ impl MyApp {
                 .changed()
             {
                 if let Err(err) = self
+                    .connection
                     .gui_cmd_tx
                     .send(GuiCommand::ShowTimestamps(self.show_sent_cmds))
                 {
@@ -1007,6 +1755,68 @@ impl MyApp {
             ui.label("Show Timestamp");
         });
         ui.add_space(5.0);
+        ui.horizontal(|ui| {
+            if ui
+                .add(toggle(&mut self.show_hex_dump))
+                .on_hover_text("Show raw traffic as offset/hex/ASCII dump in console.")
+                .changed()
+            {
+                if let Err(err) = self
+                    .connection
+                    .gui_cmd_tx
+                    .send(GuiCommand::ShowHexDump(self.show_hex_dump))
+                {
+                    log::error!("clear_tx thread send failed: {:?}", err);
+                }
+            }
+            ui.label("Hex Dump");
+        });
+        ui.add_space(5.0);
+        ui.horizontal(|ui| {
+            let old_control_chars = self.control_chars;
+            egui::ComboBox::from_id_salt("Control Characters")
+                .selected_text(self.control_chars.to_string())
+                .width(100.0)
+                .show_ui(ui, |ui| {
+                    for control_chars in ControlChars::ALL {
+                        ui.selectable_value(
+                            &mut self.control_chars,
+                            control_chars,
+                            control_chars.to_string(),
+                        );
+                    }
+                })
+                .response
+                .on_hover_text("Show control characters (e.g. line endings) in console.");
+            if self.control_chars != old_control_chars {
+                if let Err(err) = self
+                    .connection
+                    .gui_cmd_tx
+                    .send(GuiCommand::ShowControlChars(self.control_chars))
+                {
+                    log::error!("clear_tx thread send failed: {:?}", err);
+                }
+            }
+            ui.label("Control Characters");
+        });
+        ui.add_space(5.0);
+        ui.horizontal(|ui| {
+            if ui
+                .add(toggle(&mut self.pretty_print_json))
+                .on_hover_text("Indent JSON lines in console.")
+                .changed()
+            {
+                if let Err(err) = self
+                    .connection
+                    .gui_cmd_tx
+                    .send(GuiCommand::PrettyPrintJson(self.pretty_print_json))
+                {
+                    log::error!("clear_tx thread send failed: {:?}", err);
+                }
+            }
+            ui.label("Pretty Print JSON");
+        });
+        ui.add_space(5.0);
         ui.horizontal(|ui| {
             ui.label("EOL character");
             ui.add(
@@ -1114,35 +1924,17 @@ impl MyApp {
             ui.label(format!("Detected {} Datasets:", self.labels.len()));
         }
         ui.add_space(5.0);
-        for i in 0..self.labels.len().min(10) {
-            // if init, set names to what has been stored in the device last time
-            if self.init {
-                // self.names_tx.send(self.labels.clone()).expect("Failed to send names");
-                self.init = false;
-            }
-
-            if self.labels.len() <= i {
-                break;
+        // if init, set names to what has been stored in the device last time
+        if self.init {
+            // self.names_tx.send(self.labels.clone()).expect("Failed to send names");
+            self.init = false;
+        }
+        if self.serial_devices.devices[self.device_idx].parser.mode == ParseMode::Tag {
+            self.draw_channel_groups(ui);
+        } else {
+            for i in 0..self.labels.len().min(10) {
+                self.draw_dataset_label(ui, i);
             }
-            ui.horizontal(|ui| {
-                let response = color_picker_widget(ui, "", &mut self.colors, i);
-
-                // Check if the square was clicked and toggle color picker window
-                if response.clicked() {
-                    self.show_color_window = ColorWindow::ColorIndex(i);
-                };
-
-                if ui
-                    .add(
-                        egui::TextEdit::singleline(&mut self.labels[i])
-                            .desired_width(0.95 * RIGHT_PANEL_WIDTH),
-                    )
-                    .on_hover_text("Use custom names for your Datasets.")
-                    .changed()
-                {
-                    // self.names_tx.send(self.labels.clone()).expect("Failed to send names");
-                };
-            });
         }
         match self.show_color_window {
             ColorWindow::NoShow => {}
@@ -1160,6 +1952,217 @@ impl MyApp {
         if self.labels.len() > 10 {
             ui.label("Only renaming up to 10 Datasets is currently supported.");
         }
+        ui.add_space(10.0);
+        self.draw_derived_channels(ui);
+    }
+
+    /// Virtual channels computed from the columns, e.g. `P = V * I`.
+    fn draw_derived_channels(&mut self, ui: &mut Ui) {
+        let devices = self.serial_devices.devices.len();
+        self.serial_devices.derived_channels.resize(devices, vec![]);
+        let channels = &mut self.serial_devices.derived_channels[self.device_idx];
+        ui.horizontal(|ui| {
+            ui.label("Derived Channels:");
+            if ui
+                .button(egui_phosphor::regular::PLUS)
+                .on_hover_text("Add a channel computed from the columns.")
+                .clicked()
+            {
+                channels.push(DerivedChannel {
+                    name: format!("Derived {}", channels.len()),
+                    expression: String::new(),
+                });
+            }
+        });
+        let mut remove = None;
+        for (i, channel) in channels.iter_mut().enumerate() {
+            ui.horizontal(|ui| {
+                ui.add(egui::TextEdit::singleline(&mut channel.name).desired_width(70.0))
+                    .on_hover_text("Name of the channel.");
+                ui.label("=");
+                let error = Expression::parse(&channel.expression).err();
+                let text_color = if error.is_some() {
+                    Some(Color32::RED)
+                } else {
+                    None
+                };
+                let response = ui.add(
+                    egui::TextEdit::singleline(&mut channel.expression)
+                        .hint_text("V * I")
+                        .text_color_opt(text_color)
+                        .desired_width(RIGHT_PANEL_WIDTH * 0.92 - 120.0),
+                );
+                match error {
+                    Some(err) => response.on_hover_text(err),
+                    None => response.on_hover_text(
+                        "Columns by name (ax, \"Column 0\") or index ($0), + - * / % ^, pi, abs, sqrt, exp, ln, log10, sin, cos, tan, asin, acos, atan, atan2(y, x), min(a, b), max(a, b), hypot(x, y, z).\navg(x, n): mean of the last n samples\nlowpass(x, tau): low-pass with the time constant tau in s\nderiv(x): change per s\nintegral(x): integral over s",
+                    ),
+                };
+                if ui.button(egui_phosphor::regular::X).clicked() {
+                    remove = Some(i);
+                }
+            });
+        }
+        if let Some(i) = remove {
+            channels.remove(i);
+        }
+    }
+
+    /// Color and label of a dataset.
+    fn draw_dataset_label(&mut self, ui: &mut Ui, i: usize) {
+        if self.labels.len() <= i {
+            return;
+        }
+        ui.horizontal(|ui| {
+            let response = color_picker_widget(ui, "", &mut self.colors, i);
+
+            // Check if the square was clicked and toggle color picker window
+            if response.clicked() {
+                self.show_color_window = ColorWindow::ColorIndex(i);
+            };
+
+            if ui
+                .add(
+                    egui::TextEdit::singleline(&mut self.labels[i])
+                        .desired_width(0.95 * RIGHT_PANEL_WIDTH),
+                )
+                .on_hover_text("Use custom names for your Datasets.")
+                .changed()
+            {
+                // self.names_tx.send(self.labels.clone()).expect("Failed to send names");
+            };
+        });
+        let Some(column) = self.column_key(i) else {
+            return;
+        };
+        let old_settings = self.serial_devices.devices[self.device_idx].clone();
+        let parser = &mut self.serial_devices.devices[self.device_idx].parser;
+        let mut scale = parser
+            .scale(&column)
+            .cloned()
+            .unwrap_or_else(|| ColumnScale::new(column.clone()));
+        ui.horizontal(|ui| {
+            ui.add_space(25.0);
+            ui.add(
+                egui::DragValue::new(&mut scale.gain)
+                    .speed(0.01)
+                    .prefix("× "),
+            )
+            .on_hover_text("Gain, new values are raw × gain + offset.");
+            ui.add(
+                egui::DragValue::new(&mut scale.offset)
+                    .speed(0.1)
+                    .prefix("+ "),
+            )
+            .on_hover_text("Offset, new values are raw × gain + offset.");
+            ui.add(
+                egui::TextEdit::singleline(&mut scale.unit)
+                    .hint_text("Unit")
+                    .desired_width(50.0),
+            )
+            .on_hover_text("Unit shown in the legend and the CSV header.");
+        });
+        let unscaled = scale == ColumnScale::new(column.clone());
+        // columns without scaling are not stored
+        match parser
+            .scales
+            .iter()
+            .position(|known| known.column == column)
+        {
+            Some(position) if unscaled => {
+                parser.scales.remove(position);
+            }
+            Some(position) => parser.scales[position] = scale,
+            None if !unscaled => parser.scales.push(scale),
+            None => {}
+        }
+        self.apply_device_settings(&old_settings);
+    }
+
+    /// Column of a dataset in the parser settings: its index in numbers mode, else its name.
+    fn column_key(&self, i: usize) -> Option<String> {
+        let name = &self.data.plots.get(i)?.0;
+        let derived = self
+            .serial_devices
+            .derived_channels
+            .get(self.device_idx)
+            .is_some_and(|channels| channels.iter().any(|channel| channel.name == *name));
+        if derived {
+            // derived channels are not scaled
+            return None;
+        }
+        let device = &self.serial_devices.devices[self.device_idx];
+        if device.parser.mode == ParseMode::Numbers && !matches!(device.framing, Framing::Schema(_))
+        {
+            // keyed by the field, the time column is no dataset
+            Some(device.parser.field_index(i).to_string())
+        } else {
+            Some(name.clone())
+        }
+    }
+
+    /// Label of a dataset with the unit of its column, e.g. `Temperature [°C]`.
+    fn unit_label(&self, i: usize) -> String {
+        let unit = self.column_key(i).and_then(|column| {
+            self.serial_devices.devices[self.device_idx]
+                .parser
+                .scale(&column)
+                .map(|scale| scale.unit.clone())
+        });
+        match unit {
+            Some(unit) if !unit.is_empty() => format!("{} [{unit}]", self.labels[i]),
+            _ => self.labels[i].clone(),
+        }
+    }
+
+    /// Datasets by channel group (tag mode), each group with the plot it is shown in.
+    fn draw_channel_groups(&mut self, ui: &mut Ui) {
+        let mut groups: Vec<String> = vec![];
+        for (name, _) in &self.data.plots {
+            let group = channel_group(name);
+            if !groups.iter().any(|known| known == group) {
+                groups.push(group.to_string());
+            }
+        }
+        let number_of_plots = self.serial_devices.number_of_plots[self.device_idx];
+        let devices = self.serial_devices.devices.len();
+        self.serial_devices
+            .group_plots
+            .resize(devices, BTreeMap::new());
+        for group in groups {
+            ui.horizontal(|ui| {
+                ui.label(egui::RichText::new(&group).strong());
+                let group_plots = &mut self.serial_devices.group_plots[self.device_idx];
+                let plot = group_plots.get(&group).copied();
+                let text = match plot {
+                    Some(plot) => format!("Plot {}", plot + 1),
+                    None => "All Plots".to_string(),
+                };
+                egui::ComboBox::from_id_salt(format!("Group Plot {group}"))
+                    .selected_text(text)
+                    .width(80.0)
+                    .show_ui(ui, |ui| {
+                        if ui.selectable_label(plot.is_none(), "All Plots").clicked() {
+                            group_plots.remove(&group);
+                        }
+                        for i in 0..number_of_plots {
+                            if ui
+                                .selectable_label(plot == Some(i), format!("Plot {}", i + 1))
+                                .clicked()
+                            {
+                                group_plots.insert(group.clone(), i);
+                            }
+                        }
+                    })
+                    .response
+                    .on_hover_text("Plot that shows the channels of this group.");
+            });
+            for i in 0..self.data.plots.len().min(10) {
+                if channel_group(&self.data.plots[i].0) == group {
+                    self.draw_dataset_label(ui, i);
+                }
+            }
+        }
     }
 
     fn draw_highlight_settings(&mut self, ui: &mut Ui) {
@@ -1258,6 +2261,16 @@ impl MyApp {
                                 self.draw_plot_settings(ui);
                             });
 
+                        if self.serial_devices.devices[self.device_idx].parser.mode
+                            == ParseMode::Nmea
+                        {
+                            CollapsingHeader::new("GNSS Status")
+                                .default_open(true)
+                                .show(ui, |ui| {
+                                    draw_nmea_status(ui, &self.data.nmea);
+                                });
+                        }
+
                         CollapsingHeader::new("Text Highlight Settings")
                             .default_open(true)
                             .show(ui, |ui| {
@@ -1282,7 +2295,7 @@ impl MyApp {
                             let path = dropped_file.clone().path.unwrap();
                             self.picked_path = path.to_path_buf();
                             self.file_opened = true;
-                            if let Err(e) = self.load_tx.send(self.picked_path.clone()) {
+                            if let Err(e) = self.connection.load_tx.send(self.picked_path.clone()) {
                                 log::error!("load_tx thread send failed: {:?}", e);
                             }
                         }
@@ -1300,11 +2313,43 @@ impl MyApp {
                                 self.picked_path = path.to_path_buf();
                                 self.file_opened = true;
                                 self.file_dialog_state = FileDialogState::None;
-                                if let Err(e) = self.load_tx.send(self.picked_path.clone()) {
+                                if let Err(e) =
+                                    self.connection.load_tx.send(self.picked_path.clone())
+                                {
                                     log::error!("load_tx thread send failed: {:?}", e);
                                 }
                             }
                         }
+                        FileDialogState::OpenReplay => {
+                            if let Some(path) = self
+                                .file_dialog
+                                .update_with_right_panel_ui(ui.ctx(), &mut |ui, dia| {
+                                    self.information_panel.ui(ui, dia);
+                                })
+                                .picked()
+                            {
+                                self.serial_devices.devices[self.device_idx]
+                                    .simulation
+                                    .replay_file = path.to_path_buf();
+                                self.file_dialog_state = FileDialogState::None;
+                                save_serial_settings(&self.serial_devices);
+                            }
+                        }
+                        FileDialogState::SendFile => {
+                            if let Some(path) = self.file_dialog.update(ui.ctx()).picked() {
+                                self.file_dialog_state = FileDialogState::None;
+                                if let Err(err) = self.connection.serial_cmd_tx.send(
+                                    SerialCommand::SendFile(FileTransfer {
+                                        path: path.to_path_buf(),
+                                        protocol: self.transfer_protocol,
+                                        chunk_size: self.chunk_size,
+                                        chunk_delay: Duration::from_millis(self.chunk_delay_ms),
+                                    }),
+                                ) {
+                                    log::error!("serial_cmd_tx thread send failed: {:?}", err);
+                                }
+                            }
+                        }
                         FileDialogState::SavePlot => {
                             if let Some(path) = self.file_dialog.update(ui.ctx()).picked() {
                                 self.picked_path = path.to_path_buf();
@@ -1323,11 +2368,13 @@ impl MyApp {
                                 self.file_dialog_state = FileDialogState::None;
                                 self.picked_path.set_extension("csv");
 
-                                if let Err(e) = self.save_tx.send(FileOptions {
+                                if let Err(e) = self.connection.save_tx.send(FileOptions {
                                     file_path: self.picked_path.clone(),
                                     save_absolute_time: self.gui_conf.save_absolute_time,
                                     save_raw_traffic: self.save_raw,
-                                    names: self.labels.clone(),
+                                    names: (0..self.labels.len())
+                                        .map(|i| self.unit_label(i))
+                                        .collect(),
                                 }) {
                                     log::error!("save_tx thread send failed: {:?}", e);
                                 }
@@ -1339,6 +2386,113 @@ impl MyApp {
             });
     }
 
+    fn draw_file_transfer(&mut self, ui: &mut Ui) {
+        let progress = self
+            .connection
+            .transfer_lock
+            .read()
+            .map(|progress| progress.clone())
+            .unwrap_or_default();
+        let running = progress
+            .as_ref()
+            .is_some_and(|progress| progress.state == TransferState::Running);
+        ui.horizontal(|ui| {
+            if self.file_opened {
+                ui.disable();
+            }
+            ui.label("File");
+            ui.add_enabled_ui(!running, |ui| {
+                egui::ComboBox::from_id_salt("Transfer Protocol")
+                    .selected_text(self.transfer_protocol.to_string())
+                    .width(100.0)
+                    .show_ui(ui, |ui| {
+                        for protocol in Protocol::ALL {
+                            ui.selectable_value(
+                                &mut self.transfer_protocol,
+                                protocol,
+                                protocol.to_string(),
+                            );
+                        }
+                    });
+                if self.transfer_protocol == Protocol::Raw {
+                    ui.add(
+                        egui::DragValue::new(&mut self.chunk_size)
+                            .range(1..=65536)
+                            .suffix(" B"),
+                    )
+                    .on_hover_text("Size of the chunks.");
+                    ui.add(
+                        egui::DragValue::new(&mut self.chunk_delay_ms)
+                            .range(0..=10_000)
+                            .suffix(" ms"),
+                    )
+                    .on_hover_text("Delay between the chunks.");
+                }
+            });
+            if ui
+                .add_enabled(
+                    self.connected_to_device && !running,
+                    egui::Button::new(format!("{} Send file", egui_phosphor::regular::UPLOAD)),
+                )
+                .on_hover_text("Sends a file to the device.")
+                .clicked()
+            {
+                self.file_dialog_state = FileDialogState::SendFile;
+                self.file_dialog.pick_file();
+            }
+        });
+        let Some(progress) = progress else {
+            return;
+        };
+        ui.horizontal(|ui| {
+            let fraction = if progress.total == 0 {
+                1.0
+            } else {
+                progress.sent as f32 / progress.total as f32
+            };
+            let text = format!(
+                "{} ({}) {}/{} B",
+                progress.name, progress.protocol, progress.sent, progress.total
+            );
+            match &progress.state {
+                TransferState::Running => {
+                    ui.add(
+                        egui::ProgressBar::new(fraction)
+                            .text(text)
+                            .desired_width(250.0),
+                    );
+                    if ui.button("Cancel").clicked() {
+                        if let Err(err) = self
+                            .connection
+                            .serial_cmd_tx
+                            .send(SerialCommand::CancelTransfer)
+                        {
+                            log::error!("serial_cmd_tx thread send failed: {:?}", err);
+                        }
+                    }
+                    ui.ctx().request_repaint_after(Duration::from_millis(100));
+                }
+                TransferState::Done => {
+                    ui.label(format!("Sent {text}"));
+                }
+                TransferState::Failed(reason) => {
+                    ui.colored_label(Color32::RED, format!("Failed {text}"))
+                        .on_hover_text(reason);
+                }
+            }
+            if progress.state != TransferState::Running
+                && ui
+                    .button(egui_phosphor::regular::X)
+                    .on_hover_text("Clear the transfer status.")
+                    .clicked()
+            {
+                if let Ok(mut write_guard) = self.connection.transfer_lock.write() {
+                    *write_guard = None;
+                }
+            }
+        });
+    }
+
     fn paint_connection_indicator(&self, ui: &mut egui::Ui) {
         let (color, color_stroke) = if !self.connected_to_device {
             ui.add(egui::Spinner::new());
@@ -1357,9 +2511,191 @@ impl MyApp {
     }
 }
 
+/// Editor of the binary frame schema of a device.
+/// Fix and satellites of the received NMEA sentences.
+fn draw_nmea_status(ui: &mut egui::Ui, status: &NmeaStatus) {
+    let value = |value: Option<f64>, decimals: usize, unit: &str| {
+        value.map_or("-".to_string(), |value| format!("{value:.decimals$}{unit}"))
+    };
+    egui::Grid::new("nmea_status")
+        .num_columns(2)
+        .spacing(Vec2 { x: 10.0, y: 5.0 })
+        .striped(true)
+        .show(ui, |ui| {
+            ui.label("Fix");
+            let color = if status.fix_quality.unwrap_or(0) > 0 {
+                Color32::from_rgb(80, 180, 80)
+            } else {
+                Color32::RED
+            };
+            ui.label(egui::RichText::new(status.fix()).color(color).strong());
+            ui.end_row();
+            ui.label("Satellites used");
+            ui.label(value(status.satellites_used.map(f64::from), 0, ""));
+            ui.end_row();
+            ui.label("HDOP");
+            ui.label(value(status.hdop, 1, ""));
+            ui.end_row();
+            ui.label("Position");
+            ui.label(format!(
+                "{}, {}",
+                value(status.lat, 6, "°"),
+                value(status.lon, 6, "°")
+            ));
+            ui.end_row();
+            ui.label("Altitude");
+            ui.label(value(status.alt, 1, " m"));
+            ui.end_row();
+            ui.label("Speed");
+            ui.label(value(status.speed, 1, " km/h"));
+            ui.end_row();
+        });
+    if status.satellites.is_empty() {
+        return;
+    }
+    ui.add_space(5.0);
+    ui.label(format!("{} Satellites in view:", status.satellites.len()));
+    for satellite in &status.satellites {
+        ui.horizontal(|ui| {
+            ui.monospace(format!("{} {:>3}", satellite.talker, satellite.prn));
+            let snr = satellite.snr.unwrap_or(0.0);
+            ui.add(
+                egui::ProgressBar::new((snr / 50.0).clamp(0.0, 1.0) as f32)
+                    .text(value(satellite.snr, 0, " dB"))
+                    .desired_width(120.0),
+            )
+            .on_hover_text(format!(
+                "Elevation {}, azimuth {}",
+                value(satellite.elevation, 0, "°"),
+                value(satellite.azimuth, 0, "°")
+            ));
+        });
+    }
+}
+
+fn draw_frame_schema(ui: &mut egui::Ui, schema: &mut FrameSchema, bad_frames: usize) {
+    ui.horizontal(|ui| {
+        ui.label("Sync");
+        ui.add(
+            egui::TextEdit::singleline(&mut schema.sync)
+                .hint_text("\\xAA\\x55")
+                .desired_width(80.0),
+        )
+        .on_hover_text("Sync bytes at the start of each frame, use \\xHH for bytes.");
+        egui::ComboBox::from_id_salt("Length Field")
+            .selected_text(schema.length.to_string())
+            .width(90.0)
+            .show_ui(ui, |ui| {
+                for length in LengthField::ALL {
+                    ui.selectable_value(&mut schema.length, length, length.to_string());
+                }
+            })
+            .response
+            .on_hover_text(
+                "Length field after the sync bytes, holds the number of bytes of the fields.",
+            );
+    });
+    ui.horizontal(|ui| {
+        egui::ComboBox::from_id_salt("Endianness")
+            .selected_text(schema.endianness.to_string())
+            .width(100.0)
+            .show_ui(ui, |ui| {
+                for endianness in Endianness::ALL {
+                    ui.selectable_value(&mut schema.endianness, endianness, endianness.to_string());
+                }
+            });
+        egui::ComboBox::from_id_salt("Checksum")
+            .selected_text(schema.checksum.to_string())
+            .width(100.0)
+            .show_ui(ui, |ui| {
+                for checksum in Checksum::ALL {
+                    ui.selectable_value(&mut schema.checksum, checksum, checksum.to_string());
+                }
+            })
+            .response
+            .on_hover_text(
+                "Checksum at the end of a frame, calculated over the length field and the fields.",
+            );
+    });
+    let mut remove = None;
+    for (i, field) in schema.fields.iter_mut().enumerate() {
+        ui.horizontal(|ui| {
+            ui.add(
+                egui::TextEdit::singleline(&mut field.name)
+                    .hint_text(format!("Field {i}"))
+                    .desired_width(70.0),
+            );
+            egui::ComboBox::from_id_salt(format!("Field Type {i}"))
+                .selected_text(field.field_type.to_string())
+                .width(45.0)
+                .show_ui(ui, |ui| {
+                    for field_type in FieldType::ALL {
+                        ui.selectable_value(
+                            &mut field.field_type,
+                            field_type,
+                            field_type.to_string(),
+                        );
+                    }
+                });
+            ui.add(
+                egui::DragValue::new(&mut field.scale)
+                    .speed(0.01)
+                    .prefix("×"),
+            )
+            .on_hover_text("Scale, the value is raw × scale + offset.");
+            ui.add(
+                egui::DragValue::new(&mut field.offset)
+                    .speed(0.1)
+                    .prefix("+"),
+            )
+            .on_hover_text("Offset, the value is raw × scale + offset.");
+            if ui.button(egui_phosphor::regular::X).clicked() {
+                remove = Some(i);
+            }
+        });
+    }
+    if let Some(i) = remove {
+        schema.fields.remove(i);
+    }
+    ui.horizontal(|ui| {
+        if ui
+            .button(egui_phosphor::regular::PLUS)
+            .on_hover_text("Add a field.")
+            .clicked()
+        {
+            schema.fields.push(SchemaField::new(String::new()));
+        }
+        ui.label(format!("{} bytes per frame", schema.frame_len()));
+        let color = if bad_frames > 0 {
+            Color32::RED
+        } else {
+            ui.visuals().text_color()
+        };
+        ui.label(egui::RichText::new(format!("{bad_frames} bad frames")).color(color))
+            .on_hover_text("Frames with wrong sync bytes, length or checksum.");
+    });
+}
+
+/// Paints the state of a modem input line, hollow if the line is not reported by the port.
+fn paint_led(ui: &mut egui::Ui, level: Option<bool>) {
+    let size = ui.spacing().interact_size.y * 0.6;
+    let (rect, _response) = ui.allocate_exact_size(egui::vec2(size, size), egui::Sense::hover());
+    let (color, color_stroke) = match level {
+        Some(true) => (Color32::DARK_GREEN, Color32::GREEN),
+        Some(false) => (Color32::DARK_GRAY, Color32::GRAY),
+        None => (Color32::TRANSPARENT, Color32::GRAY),
+    };
+    ui.painter().circle(
+        rect.center(),
+        size * 0.5,
+        color,
+        egui::Stroke::new(1.0, color_stroke),
+    );
+}
+
 impl eframe::App for MyApp {
     fn ui(&mut self, ui: &mut egui::Ui, frame: &mut eframe::Frame) {
-        if let Ok(read_guard) = self.connected_lock.read() {
+        if let Ok(read_guard) = self.connection.connected_lock.read() {
             self.connected_to_device = *read_guard;
         }
 
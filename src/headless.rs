@@ -0,0 +1,333 @@
+use crossbeam_channel::{Receiver, RecvTimeoutError, Sender};
+use csv::{Writer, WriterBuilder};
+use regex::Regex;
+use std::error::Error;
+use std::fs::File;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::data::{Packet, SerialDirection};
+use crate::encoding::ControlChars;
+use crate::framing::Framing;
+use crate::io::{raw_traffic_record, RAW_TRAFFIC_HEADER};
+use crate::parser::{parse_fields, ParseMode, Parser, ParserSettings, TimeBase};
+use crate::schema::FrameSchema;
+use crate::serial::{serial_devices_thread, serial_thread, Device, ModemLines, SerialCommand};
+use crate::{console_text, split};
+
+/// Give up when the port can not be opened (again) within this time.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+/// How often the connection is checked while waiting for data.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// What to record without GUI and when to stop.
+pub struct HeadlessOptions {
+    /// Print the traffic to stdout.
+    pub print: bool,
+    /// Stream the parsed data to this CSV file.
+    pub csv_file: Option<PathBuf>,
+    /// Stream the raw traffic to this CSV file.
+    pub raw_file: Option<PathBuf>,
+    pub duration: Option<Duration>,
+    /// Stop once a received line matches.
+    pub stop_on: Option<Regex>,
+    /// Commands (with line ending) sent after connecting.
+    pub commands: Vec<String>,
+    pub serial_commands: Vec<SerialCommand>,
+    pub column_labels: Vec<String>,
+}
+
+/// Logs to stderr, as there is no GUI to show the log.
+struct StderrLogger;
+
+impl log::Log for StderrLogger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        metadata.level() <= log::Level::Info
+    }
+
+    fn log(&self, record: &log::Record) {
+        if self.enabled(record.metadata()) {
+            eprintln!("[{}] {}", record.level(), record.args());
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+static LOGGER: StderrLogger = StderrLogger;
+
+pub fn init_logger() {
+    if log::set_logger(&LOGGER).is_ok() {
+        log::set_max_level(log::LevelFilter::Info);
+    }
+}
+
+/// Turns the packets into the values of a CSV row like the GUI does: by the frame schema or
+/// the parse mode, with the time column and the scaling of the device.
+struct RowParser {
+    settings: ParserSettings,
+    parser: Parser,
+    time_base: TimeBase,
+    schema: Option<FrameSchema>,
+}
+
+impl RowParser {
+    fn new(device: &Device) -> Self {
+        RowParser {
+            settings: device.parser.clone(),
+            parser: Parser::new(&device.parser),
+            time_base: TimeBase::new(&device.parser),
+            schema: match &device.framing {
+                Framing::Schema(schema) => Some(schema.clone()),
+                _ => None,
+            },
+        }
+    }
+
+    /// Values are taken by position, their names are the column indices.
+    fn indexed(&self) -> bool {
+        self.schema.is_none() && self.settings.mode == ParseMode::Numbers
+    }
+
+    /// Returns the time in ms and the named values of a packet, `None` if it has no data.
+    fn row(&mut self, packet: &Packet) -> Option<(f64, Vec<(String, f64)>)> {
+        if let Some(schema) = &self.schema {
+            if packet.direction != SerialDirection::Receive {
+                return None;
+            }
+            let values = schema.decode(&packet.raw).ok()?;
+            let mut values: Vec<(String, f64)> = schema.names().into_iter().zip(values).collect();
+            let time = self
+                .time_base
+                .take_named(&mut values, packet.relative_time)?;
+            self.settings.scale_named(&mut values);
+            return Some((time, values));
+        }
+        if let Some(mut values) = self.parser.named_values(&packet.payload) {
+            // lines without values (e.g. log messages) are only printed
+            if packet.direction != SerialDirection::Receive || values.is_empty() {
+                return None;
+            }
+            let time = self
+                .time_base
+                .take_named(&mut values, packet.relative_time)?;
+            self.settings.scale_named(&mut values);
+            return Some((time, values));
+        }
+        let mut values = if self.settings.robust {
+            if packet.direction != SerialDirection::Receive {
+                return None;
+            }
+            parse_fields(&packet.payload, &self.settings.number_format)?
+        } else {
+            split(&packet.payload, &self.settings.number_format)
+        };
+        if values.iter().all(|value| value.is_nan()) {
+            return None;
+        }
+        let time = self
+            .time_base
+            .take_indexed(&mut values, packet.relative_time)?;
+        self.settings.scale_indexed(&mut values);
+        Some((
+            time,
+            values
+                .into_iter()
+                .enumerate()
+                .map(|(i, value)| (self.settings.field_index(i).to_string(), value))
+                .collect(),
+        ))
+    }
+
+    /// Header of a column, with the unit of its scaling. In numbers mode the column is the
+    /// index of its field.
+    fn label(&self, column: &str, column_labels: &[String]) -> String {
+        let label = match column.parse::<usize>() {
+            Ok(i) if self.indexed() => column_labels
+                .get(i)
+                .cloned()
+                .unwrap_or_else(|| format!("Column {i}")),
+            _ => column.to_string(),
+        };
+        match self.settings.scale(column) {
+            Some(scale) if !scale.unit.is_empty() => format!("{label} [{}]", scale.unit),
+            _ => label,
+        }
+    }
+}
+
+/// Records the traffic of `device` until the duration is over, the stop pattern has been
+/// received or the serial thread exits. Fails if the port can not be opened.
+pub fn run_headless(device: Device, options: HeadlessOptions) -> Result<(), Box<dyn Error>> {
+    let t_zero = Instant::now();
+    let deadline = options.duration.map(|duration| t_zero + duration);
+    let name = device.name.clone();
+    let mut row_parser = RowParser::new(&device);
+
+    let devices_lock = Arc::new(RwLock::new(vec![]));
+    let device_lock = Arc::new(RwLock::new(device));
+    let connected_lock = Arc::new(RwLock::new(false));
+    let modem_lock = Arc::new(RwLock::new(ModemLines::default()));
+    let transfer_lock = Arc::new(RwLock::new(None));
+
+    let (send_tx, send_rx): (Sender<String>, Receiver<String>) = crossbeam_channel::unbounded();
+    let (serial_cmd_tx, serial_cmd_rx): (Sender<SerialCommand>, Receiver<SerialCommand>) =
+        crossbeam_channel::unbounded();
+    let (raw_data_tx, raw_data_rx): (Sender<Packet>, Receiver<Packet>) =
+        crossbeam_channel::unbounded();
+
+    let serial_devices_lock = devices_lock.clone();
+    thread::spawn(|| {
+        serial_devices_thread(serial_devices_lock);
+    });
+    let serial_device_lock = device_lock.clone();
+    let serial_connected_lock = connected_lock.clone();
+    thread::spawn(move || {
+        serial_thread(
+            send_rx,
+            serial_cmd_rx,
+            raw_data_tx,
+            serial_device_lock,
+            devices_lock,
+            serial_connected_lock,
+            modem_lock,
+            transfer_lock,
+            t_zero,
+        );
+    });
+
+    // executed by the serial thread once the port is open
+    for cmd in options.serial_commands {
+        serial_cmd_tx.send(cmd)?;
+    }
+    for command in options.commands {
+        send_tx.send(command)?;
+    }
+
+    let mut csv_writer = match &options.csv_file {
+        Some(path) => Some(WriterBuilder::new().has_headers(false).from_path(path)?),
+        None => None,
+    };
+    let mut raw_writer = match &options.raw_file {
+        Some(path) => {
+            let mut writer = WriterBuilder::new().has_headers(false).from_path(path)?;
+            writer.write_record(RAW_TRAFFIC_HEADER)?;
+            writer.flush()?;
+            Some(writer)
+        }
+        None => None,
+    };
+    // data columns, set by the first line that contains data
+    let mut columns = None;
+    let mut was_connected = false;
+    let mut disconnected_since = Some(t_zero);
+
+    loop {
+        if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            log::info!("Recording time is over");
+            break;
+        }
+        // the serial thread keeps retrying, give up instead of waiting forever
+        let connected = connected_lock.read().is_ok_and(|connected| *connected);
+        if connected {
+            was_connected = true;
+            disconnected_since = None;
+        } else {
+            let since = *disconnected_since.get_or_insert_with(Instant::now);
+            if since.elapsed() >= CONNECT_TIMEOUT {
+                return Err(if was_connected {
+                    format!("lost the connection to {name}").into()
+                } else {
+                    format!("could not open {name}").into()
+                });
+            }
+        }
+        let packet = match raw_data_rx.recv_timeout(POLL_INTERVAL) {
+            Ok(packet) => packet,
+            Err(RecvTimeoutError::Timeout) => continue,
+            Err(RecvTimeoutError::Disconnected) => break,
+        };
+        if packet.payload.is_empty() {
+            continue;
+        }
+        if options.print {
+            if let Some(text) = console_text(true, true, ControlChars::AsIs, false, &packet) {
+                let mut stdout = std::io::stdout().lock();
+                stdout.write_all(text.as_bytes())?;
+                stdout.flush()?;
+            }
+        }
+        if let Some(writer) = &mut raw_writer {
+            writer.write_record(raw_traffic_record(&packet))?;
+            writer.flush()?;
+        }
+        if packet.direction == SerialDirection::Break {
+            continue;
+        }
+        if let Some(writer) = &mut csv_writer {
+            write_data_row(
+                writer,
+                &packet,
+                &mut row_parser,
+                &mut columns,
+                &options.column_labels,
+            )?;
+        }
+        if packet.direction == SerialDirection::Receive
+            && options
+                .stop_on
+                .as_ref()
+                .is_some_and(|stop_on| stop_on.is_match(&packet.payload))
+        {
+            log::info!("Received the stop pattern: {}", packet.payload);
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Appends the values of a line to the data CSV. The header is written with the first line,
+/// later lines fill the columns by name. In numbers mode lines with a different number of
+/// values are skipped.
+fn write_data_row(
+    writer: &mut Writer<File>,
+    packet: &Packet,
+    row_parser: &mut RowParser,
+    columns: &mut Option<Vec<String>>,
+    column_labels: &[String],
+) -> Result<(), Box<dyn Error>> {
+    let Some((time, values)) = row_parser.row(packet) else {
+        return Ok(());
+    };
+    if columns.is_none() {
+        let mut header = vec!["Time [ms]".to_string()];
+        header.extend(
+            values
+                .iter()
+                .map(|(column, _)| row_parser.label(column, column_labels)),
+        );
+        writer.write_record(&header)?;
+        *columns = Some(values.iter().map(|(column, _)| column.clone()).collect());
+    }
+    let Some(columns) = columns else {
+        return Ok(());
+    };
+    if row_parser.indexed() && !row_parser.settings.robust && values.len() != columns.len() {
+        return Ok(());
+    }
+    let mut row = vec![format!("{time:.3}")];
+    row.extend(columns.iter().map(|column| {
+        values
+            .iter()
+            .find(|(name, _)| name == column)
+            .filter(|(_, value)| !value.is_nan())
+            .map_or(String::new(), |(_, value)| value.to_string())
+    }));
+    writer.write_record(&row)?;
+    writer.flush()?;
+    row_parser.time_base.commit();
+    Ok(())
+}
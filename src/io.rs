@@ -1,8 +1,9 @@
 use std::error::Error;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use csv::{ReaderBuilder, WriterBuilder};
 
+use crate::data::Packet;
 use crate::DataContainer;
 
 /// A set of options for saving data to a CSV file.
@@ -90,10 +91,11 @@ pub fn save_to_csv(data: &DataContainer, csv_options: &FileOptions) -> Result<()
         .min(data.absolute_time.len());
 
     for j in 0..data_rows {
+        // times are in ms with µs resolution
         let time = if csv_options.save_absolute_time {
-            data.absolute_time[j].to_string()
+            format!("{:.3}", data.absolute_time[j])
         } else {
-            data.time[j].to_string()
+            format!("{:.3}", data.time[j])
         };
         let mut data_to_write = vec![time];
         for value in data.dataset.iter() {
@@ -103,37 +105,111 @@ pub fn save_to_csv(data: &DataContainer, csv_options: &FileOptions) -> Result<()
     }
     wtr.flush()?;
     if csv_options.save_raw_traffic {
-        let mut path = csv_options.file_path.clone();
-        let mut file_name = path
-            .file_name()
-            .unwrap()
-            .to_str()
-            .unwrap()
-            .to_string()
-            .replace(".csv", "");
-        file_name += "raw.csv";
-        path.set_file_name(file_name);
-        save_raw(data, &path)?
+        save_raw(data, &sibling_path(&csv_options.file_path, "raw"))?
     }
     Ok(())
 }
 
+/// Saves each channel group to its own file, named after the group (e.g. `data_IMU.csv`).
+/// `groups` holds the group of each column, a group has a row for every sample with a value.
+pub fn save_groups_to_csv(
+    data: &DataContainer,
+    csv_options: &FileOptions,
+    groups: &[&str],
+) -> Result<(), Box<dyn Error>> {
+    let mut unique_groups: Vec<&str> = vec![];
+    for group in groups {
+        if !unique_groups.contains(group) {
+            unique_groups.push(group);
+        }
+    }
+    for group in unique_groups {
+        let columns: Vec<usize> = (0..data.dataset.len())
+            .filter(|i| groups.get(*i) == Some(&group))
+            .collect();
+        let rows: Vec<usize> = (0..data.time.len().min(data.absolute_time.len()))
+            .filter(|j| {
+                columns.iter().any(|i| {
+                    data.dataset[*i]
+                        .get(*j)
+                        .is_some_and(|value| !value.is_nan())
+                })
+            })
+            .collect();
+        let group_data = DataContainer {
+            time: rows.iter().map(|j| data.time[*j]).collect(),
+            absolute_time: rows.iter().map(|j| data.absolute_time[*j]).collect(),
+            dataset: columns
+                .iter()
+                .map(|i| rows.iter().map(|j| data.dataset[*i][*j]).collect())
+                .collect(),
+            ..DataContainer::default()
+        };
+        let group_options = FileOptions {
+            file_path: sibling_path(&csv_options.file_path, &format!("_{group}")),
+            save_absolute_time: csv_options.save_absolute_time,
+            save_raw_traffic: false,
+            names: columns
+                .iter()
+                .map(|i| {
+                    csv_options
+                        .names
+                        .get(*i)
+                        .cloned()
+                        .unwrap_or_else(|| format!("Column {i}"))
+                })
+                .collect(),
+        };
+        save_to_csv(&group_data, &group_options)?;
+    }
+    if csv_options.save_raw_traffic {
+        save_raw(data, &sibling_path(&csv_options.file_path, "raw"))?
+    }
+    Ok(())
+}
+
+/// Returns the path of a file next to `path` with `suffix` added to its name,
+/// e.g. `data_IMU.csv` for `data.csv`. Characters not allowed in file names are replaced.
+fn sibling_path(path: &Path, suffix: &str) -> PathBuf {
+    let file_name = path
+        .file_name()
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .to_string()
+        .replace(".csv", "");
+    let suffix: String = suffix
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || matches!(c, '_' | '-' | '.') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    path.with_file_name(format!("{file_name}{suffix}.csv"))
+}
+
+pub const RAW_TRAFFIC_HEADER: [&str; 4] =
+    ["Time [ms]", "Abs Time [ms]", "Direction", "Raw Traffic"];
+
+/// A row of the raw traffic CSV, the direction is `RECV`, `SEND` or `BRK`.
+pub fn raw_traffic_record(packet: &Packet) -> Vec<String> {
+    vec![
+        format!("{:.3}", packet.relative_time),
+        format!("{:.3}", packet.absolute_time),
+        packet.direction.to_string(),
+        packet.payload.clone(),
+    ]
+}
+
 pub fn save_raw(data: &DataContainer, path: &PathBuf) -> Result<(), Box<dyn Error>> {
     let mut wtr = WriterBuilder::new().has_headers(false).from_path(path)?;
-    let header = vec![
-        "Time [ms]".to_string(),
-        "Abs Time [ms]".to_string(),
-        "Raw Traffic".to_string(),
-    ];
-    wtr.write_record(header)?;
+    wtr.write_record(RAW_TRAFFIC_HEADER)?;
 
     for packet in &data.raw_traffic {
-        let mut data_to_write = vec![
-            packet.relative_time.to_string(),
-            packet.absolute_time.to_string(),
-        ];
-        data_to_write.push(packet.payload.clone());
-        wtr.write_record(&data_to_write)?;
+        wtr.write_record(raw_traffic_record(packet))?;
     }
     wtr.flush()?;
     Ok(())
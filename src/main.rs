@@ -5,29 +5,53 @@ extern crate csv;
 extern crate preferences;
 extern crate serde;
 
+use crate::connection::start_connection;
 use crate::data::{DataContainer, GuiOutputDataContainer, Packet, SerialDirection};
+use crate::derived::DerivedChannels;
+use crate::encoding::ControlChars;
+use crate::framing::Framing;
 use crate::gui::{load_gui_settings, GuiCommand, MyApp, RIGHT_PANEL_WIDTH};
-use crate::io::{open_from_csv, save_to_csv, FileOptions};
-use crate::serial::{load_serial_settings, serial_devices_thread, serial_thread, Device};
+use crate::headless::{init_logger, run_headless, HeadlessOptions};
+use crate::io::{open_from_csv, save_groups_to_csv, save_to_csv, FileOptions};
+use crate::nmea::{parse_sentence, NmeaError, NmeaStatus};
+use crate::parser::{
+    channel_group, parse_fields, pretty_json, NumberFormat, ParseMode, Parser, ParserSettings,
+    TimeBase,
+};
+use crate::schema::FrameSchema;
+use crate::serial::{load_serial_settings, serial_devices_thread, Device, PortInfo, SerialCommand};
 use crossbeam_channel::{select, Receiver, Sender};
 use eframe::egui::{vec2, ViewportBuilder};
 use eframe::{egui, icon_data};
 use egui_plot::PlotPoint;
 pub use gumdrop::Options;
 use preferences::AppInfo;
+use regex::Regex;
 use std::cmp::max;
 use std::path::PathBuf;
 use std::sync::{Arc, RwLock};
 use std::thread;
+use std::time::{Duration, Instant};
 
 mod color_picker;
+mod connection;
 mod custom_highlighter;
 mod data;
+mod derived;
+mod encoding;
+mod framing;
 mod gui;
+mod headless;
 mod io;
+mod network;
+mod nmea;
+mod parser;
+mod schema;
 mod serial;
 mod settings_window;
+mod simulation;
 mod toggle;
+mod transfer;
 mod update;
 
 const APP_INFO: AppInfo = AppInfo {
@@ -37,37 +61,345 @@ const APP_INFO: AppInfo = AppInfo {
 const PREFERENCES_KEY: &str = "config/gui";
 const PREFERENCES_KEY_SERIAL: &str = "config/serial_devices";
 
-fn split(payload: &str) -> Vec<f32> {
-    let mut split_data: Vec<&str> = vec![];
-    for s in payload.split(':') {
-        split_data.extend(s.split(','));
-    }
-    split_data
-        .iter()
+fn split(payload: &str, format: &NumberFormat) -> Vec<f64> {
+    payload
+        .split(format.separators())
         .map(|x| x.trim())
-        .flat_map(|x| x.parse::<f32>())
+        .flat_map(|x| format.parse(x))
         .collect()
 }
 
-fn console_text(show_timestamps: bool, show_sent_cmds: bool, packet: &Packet) -> Option<String> {
+fn console_text(
+    show_timestamps: bool,
+    show_sent_cmds: bool,
+    control_chars: ControlChars,
+    pretty_print_json: bool,
+    packet: &Packet,
+) -> Option<String> {
+    // the raw bytes include the line ending, which is what we want to see
+    let text = if control_chars != ControlChars::AsIs && !packet.raw.is_empty() {
+        control_chars.visualize(&packet.encoding.decode(&packet.raw))
+    } else if pretty_print_json {
+        pretty_json(&packet.payload).unwrap_or_else(|| packet.payload.clone())
+    } else {
+        packet.payload.clone()
+    };
     match (show_sent_cmds, show_timestamps, &packet.direction) {
         (true, true, _) => Some(format!(
-            "[{}] t + {:.3}s: {}\n",
+            "[{}] t + {:.6}s: {}\n",
             packet.direction,
-            packet.relative_time as f32 / 1000.0,
-            packet.payload
+            packet.relative_time / 1000.0,
+            text
         )),
-        (true, false, _) => Some(format!("[{}]: {}\n", packet.direction, packet.payload)),
+        (true, false, _) => Some(format!("[{}]: {}\n", packet.direction, text)),
         (false, true, SerialDirection::Receive) => Some(format!(
-            "t + {:.3}s: {}\n",
-            packet.relative_time as f32 / 1000.0,
-            packet.payload
+            "t + {:.6}s: {}\n",
+            packet.relative_time / 1000.0,
+            text
+        )),
+        (false, false, SerialDirection::Receive) => Some(text + "\n"),
+        // breaks are events on the line, they are always shown
+        (false, true, SerialDirection::Break) => Some(format!(
+            "[{}] t + {:.6}s: {}\n",
+            packet.direction,
+            packet.relative_time / 1000.0,
+            text
         )),
-        (false, false, SerialDirection::Receive) => Some(packet.payload.clone() + "\n"),
+        (false, false, SerialDirection::Break) => {
+            Some(format!("[{}]: {}\n", packet.direction, text))
+        }
         (_, _, _) => None,
     }
 }
 
+/// Formats the raw bytes of a packet as a classic offset/hex/ASCII dump (16 bytes per row).
+fn console_hex_dump(show_timestamps: bool, show_sent_cmds: bool, packet: &Packet) -> Vec<String> {
+    if !show_sent_cmds && packet.direction == SerialDirection::Send {
+        return vec![];
+    }
+    let mut lines = vec![];
+    match (show_sent_cmds, show_timestamps) {
+        (true, true) => lines.push(format!(
+            "[{}] t + {:.6}s:\n",
+            packet.direction,
+            packet.relative_time / 1000.0
+        )),
+        (true, false) => lines.push(format!("[{}]:\n", packet.direction)),
+        (false, true) => lines.push(format!("t + {:.6}s:\n", packet.relative_time / 1000.0)),
+        (false, false) => {}
+    }
+    if packet.direction == SerialDirection::Break {
+        lines.push(format!("{}\n", packet.payload));
+        return lines;
+    }
+    for (row, chunk) in packet.raw.chunks(16).enumerate() {
+        let hex: Vec<String> = chunk.iter().map(|b| format!("{b:02x}")).collect();
+        let ascii: String = chunk
+            .iter()
+            .map(|b| {
+                if b.is_ascii_graphic() || *b == b' ' {
+                    *b as char
+                } else {
+                    '.'
+                }
+            })
+            .collect();
+        lines.push(format!(
+            "{:08x}  {:<47}  |{}|\n",
+            row * 16,
+            hex.join(" "),
+            ascii
+        ));
+    }
+    lines
+}
+
+fn console_lines(
+    show_timestamps: bool,
+    show_sent_cmds: bool,
+    show_hex_dump: bool,
+    control_chars: ControlChars,
+    pretty_print_json: bool,
+    packet: &Packet,
+) -> Vec<String> {
+    if show_hex_dump {
+        console_hex_dump(show_timestamps, show_sent_cmds, packet)
+    } else {
+        console_text(
+            show_timestamps,
+            show_sent_cmds,
+            control_chars,
+            pretty_print_json,
+            packet,
+        )
+        .into_iter()
+        .collect()
+    }
+}
+
+/// Appends the named values of a line, a column is added for each new name.
+/// Samples without a value for a column hold NaN.
+fn append_named_values(
+    data: &mut DataContainer,
+    names: &mut Vec<String>,
+    pairs: &[(String, f64)],
+    time: f64,
+    packet: &Packet,
+    data_lock: &Arc<RwLock<GuiOutputDataContainer>>,
+) {
+    let new_names: Vec<String> = pairs
+        .iter()
+        .map(|(name, _)| name)
+        .filter(|name| !names.contains(name))
+        .cloned()
+        .collect();
+    for _ in &new_names {
+        data.dataset.push(vec![f64::NAN; data.time.len()]);
+    }
+    names.extend(new_names.iter().cloned());
+
+    data.time.push(time);
+    data.absolute_time.push(packet.absolute_time);
+    let values: Vec<Option<f64>> = names
+        .iter()
+        .map(|name| {
+            pairs
+                .iter()
+                .find(|(pair_name, _)| pair_name == name)
+                .map(|(_, value)| *value)
+        })
+        .collect();
+    for (set, value) in data.dataset.iter_mut().zip(&values) {
+        set.push(value.unwrap_or(f64::NAN));
+    }
+
+    if let Ok(mut gui_data) = data_lock.write() {
+        // the plots of the derived channels stay behind the columns
+        let at = data.dataset.len() - new_names.len();
+        let new_plots = new_names.into_iter().map(|name| {
+            // earlier samples are gaps, this keeps the series aligned
+            let graph = data.time[..data.time.len() - 1]
+                .iter()
+                .map(|t| PlotPoint {
+                    x: t / 1000.0,
+                    y: f64::NAN,
+                })
+                .collect();
+            (name, graph)
+        });
+        gui_data.plots.splice(at..at, new_plots);
+        for ((_label, graph), value) in gui_data.plots.iter_mut().zip(&values) {
+            graph.push(PlotPoint {
+                x: time / 1000.0,
+                y: value.unwrap_or(f64::NAN),
+            });
+        }
+    }
+}
+
+/// Label of a column of the numbers mode, `--column` labels are by field index.
+fn column_label(cli_column_labels: &[String], settings: &ParserSettings, column: usize) -> String {
+    let field = settings.field_index(column);
+    cli_column_labels
+        .get(field)
+        .cloned()
+        .unwrap_or_else(|| format!("Column {field}"))
+}
+
+/// Appends the values of a line in the robust numbers mode. Columns are added when a line has
+/// more values, missing values are NaN and leave a gap in the plot.
+fn append_values(
+    data: &mut DataContainer,
+    values: &[f64],
+    time: f64,
+    packet: &Packet,
+    data_lock: &Arc<RwLock<GuiOutputDataContainer>>,
+    settings: &ParserSettings,
+    cli_column_labels: &[String],
+) {
+    let new_columns = data.dataset.len()..values.len();
+    for _ in new_columns.clone() {
+        data.dataset.push(vec![f64::NAN; data.time.len()]);
+    }
+    data.time.push(time);
+    data.absolute_time.push(packet.absolute_time);
+    for (i, set) in data.dataset.iter_mut().enumerate() {
+        set.push(values.get(i).copied().unwrap_or(f64::NAN));
+    }
+
+    if let Ok(mut gui_data) = data_lock.write() {
+        for i in new_columns {
+            let label = column_label(cli_column_labels, settings, i);
+            // earlier samples are gaps, this keeps the series aligned
+            let graph = data.time[..data.time.len() - 1]
+                .iter()
+                .map(|t| PlotPoint {
+                    x: t / 1000.0,
+                    y: f64::NAN,
+                })
+                .collect();
+            gui_data.plots.insert(i, (label, graph));
+        }
+        for ((_label, graph), set) in gui_data.plots.iter_mut().zip(&data.dataset) {
+            if let Some(y) = set.last() {
+                graph.push(PlotPoint {
+                    x: time / 1000.0,
+                    y: *y,
+                });
+            }
+        }
+    }
+}
+
+/// Evaluates the derived channels for the last sample and appends their plot points, their
+/// plots follow the plots of the columns.
+fn append_derived(
+    data: &DataContainer,
+    derived: &mut DerivedChannels,
+    data_lock: &Arc<RwLock<GuiOutputDataContainer>>,
+) {
+    let (Some(time), Ok(mut gui_data)) = (data.time.last(), data_lock.write()) else {
+        return;
+    };
+    if derived.channels().is_empty() {
+        return;
+    }
+    let columns = column_names(&gui_data, data);
+    let values: Vec<f64> = data
+        .dataset
+        .iter()
+        .map(|set| set.last().copied().unwrap_or(f64::NAN))
+        .collect();
+    let derived_values = derived.push(*time, &columns, &values);
+    for ((_label, graph), y) in gui_data
+        .plots
+        .iter_mut()
+        .skip(data.dataset.len())
+        .zip(derived_values)
+    {
+        graph.push(PlotPoint {
+            x: time / 1000.0,
+            y,
+        });
+    }
+}
+
+/// Evaluates the derived channels for all samples, e.g. after they have been changed,
+/// and replaces their plots.
+fn recompute_derived(
+    data: &DataContainer,
+    derived: &mut DerivedChannels,
+    data_lock: &Arc<RwLock<GuiOutputDataContainer>>,
+) {
+    derived.clear();
+    if let Ok(mut gui_data) = data_lock.write() {
+        let columns = column_names(&gui_data, data);
+        for (row, time) in data.time.iter().enumerate() {
+            let values: Vec<f64> = data
+                .dataset
+                .iter()
+                .map(|set| set.get(row).copied().unwrap_or(f64::NAN))
+                .collect();
+            derived.push(*time, &columns, &values);
+        }
+        gui_data.plots.truncate(data.dataset.len());
+        let plots: Vec<(String, Vec<PlotPoint>)> = derived
+            .names()
+            .into_iter()
+            .zip(&derived.dataset)
+            .map(|(name, set)| {
+                let graph = data
+                    .time
+                    .iter()
+                    .zip(set)
+                    .map(|(t, y)| PlotPoint {
+                        x: t / 1000.0,
+                        y: *y,
+                    })
+                    .collect();
+                (name, graph)
+            })
+            .collect();
+        gui_data.plots.extend(plots);
+    }
+}
+
+/// Names of the columns the derived channels refer to.
+fn column_names(gui_data: &GuiOutputDataContainer, data: &DataContainer) -> Vec<String> {
+    (0..data.dataset.len())
+        .map(|i| {
+            gui_data
+                .plots
+                .get(i)
+                .map_or_else(|| format!("Column {i}"), |(name, _)| name.clone())
+        })
+        .collect()
+}
+
+/// Renders the whole console from the recorded traffic, e.g. after the view has changed.
+fn console_prints(
+    raw_traffic: &[Packet],
+    show_timestamps: bool,
+    show_sent_cmds: bool,
+    show_hex_dump: bool,
+    control_chars: ControlChars,
+    pretty_print_json: bool,
+) -> Vec<String> {
+    raw_traffic
+        .iter()
+        .flat_map(|packet| {
+            console_lines(
+                show_timestamps,
+                show_sent_cmds,
+                show_hex_dump,
+                control_chars,
+                pretty_print_json,
+                packet,
+            )
+        })
+        .collect()
+}
+
 fn main_thread(
     sync_tx: Sender<bool>,
     data_lock: Arc<RwLock<GuiOutputDataContainer>>,
@@ -76,6 +408,7 @@ fn main_thread(
     load_rx: Receiver<PathBuf>,
     load_names_tx: Sender<Vec<String>>,
     gui_cmd_rx: Receiver<GuiCommand>,
+    device_lock: Arc<RwLock<Device>>,
     cli_column_labels: Vec<String>,
 ) {
     // reads data from mutex, samples and saves if needed
@@ -84,6 +417,19 @@ fn main_thread(
 
     let mut show_timestamps = true;
     let mut show_sent_cmds = true;
+    let mut show_hex_dump = false;
+    let mut control_chars = ControlChars::default();
+    let mut pretty_print_json = false;
+
+    let mut parser_settings = ParserSettings::default();
+    let mut parser = Parser::new(&parser_settings);
+    let mut time_base = TimeBase::new(&parser_settings);
+    // schema of binary frames, they are decoded instead of parsed
+    let mut schema: Option<FrameSchema> = None;
+    // column names of the named values
+    let mut names: Vec<String> = vec![];
+    // computed channels, their values follow the columns
+    let mut derived = DerivedChannels::default();
 
     let mut file_opened = false;
 
@@ -97,34 +443,166 @@ fn main_thread(
                             sync_tx.send(true).expect("unable to send sync tx");
                             data.raw_traffic.push(packet.clone());
 
-                            if let Some(text) = console_text(show_timestamps, show_sent_cmds, &packet) {
+                            let lines = console_lines(show_timestamps, show_sent_cmds, show_hex_dump, control_chars, pretty_print_json, &packet);
+                            if !lines.is_empty() {
                                 // append prints
                                 if let Ok(mut gui_data) = data_lock.write() {
-                                    gui_data.prints.push(text);
+                                    gui_data.prints.extend(lines);
+                                }
+                            }
+
+                            if packet.direction == SerialDirection::Break {
+                                // no data to plot
+                                continue;
+                            }
+
+                            let mut parser_changed = false;
+                            if let Ok(device) = device_lock.read() {
+                                if device.parser.without_scales() != parser_settings.without_scales() {
+                                    parser = Parser::new(&device.parser);
+                                    time_base = TimeBase::new(&device.parser);
+                                    parser_changed = true;
+                                }
+                                // the scaling only applies to new samples
+                                if device.parser != parser_settings {
+                                    parser_settings = device.parser.clone();
+                                }
+                                let device_schema = match &device.framing {
+                                    Framing::Schema(schema) => Some(schema),
+                                    _ => None,
+                                };
+                                if device_schema != schema.as_ref() {
+                                    schema = device_schema.cloned();
+                                    parser_changed = true;
+                                }
+                            }
+                            if parser_changed {
+                                // the columns of the previous parser do not fit anymore
+                                data.time = vec![];
+                                data.absolute_time = vec![];
+                                data.dataset = vec![];
+                                names.clear();
+                                failed_format_counter = 0;
+                                if let Ok(mut gui_data) = data_lock.write() {
+                                    gui_data.plots = vec![];
+                                    gui_data.bad_frames = 0;
+                                    gui_data.malformed_lines = 0;
+                                    gui_data.bad_checksums = 0;
+                                    gui_data.nmea = NmeaStatus::default();
                                 }
+                                recompute_derived(&data, &mut derived, &data_lock);
                             }
 
-                            let split_data = split(&packet.payload);
+                            if let Some(schema) = &schema {
+                                if packet.direction == SerialDirection::Receive {
+                                    match schema.decode(&packet.raw) {
+                                        Ok(values) => {
+                                            let mut values: Vec<(String, f64)> = schema.names().into_iter().zip(values).collect();
+                                            if let Some(time) = time_base.take_named(&mut values, packet.relative_time) {
+                                                time_base.commit();
+                                                parser_settings.scale_named(&mut values);
+                                                append_named_values(&mut data, &mut names, &values, time, &packet, &data_lock);
+                                                append_derived(&data, &mut derived, &data_lock);
+                                            }
+                                        }
+                                        Err(_) => {
+                                            if let Ok(mut gui_data) = data_lock.write() {
+                                                gui_data.bad_frames += 1;
+                                            }
+                                        }
+                                    }
+                                }
+                                continue;
+                            }
+
+                            if parser_settings.mode == ParseMode::Nmea && packet.direction == SerialDirection::Receive {
+                                match parse_sentence(&packet.payload) {
+                                    Ok(sentence) => {
+                                        if let Ok(mut gui_data) = data_lock.write() {
+                                            gui_data.nmea.update(&sentence);
+                                        }
+                                    }
+                                    Err(NmeaError::Checksum { .. }) => {
+                                        if let Ok(mut gui_data) = data_lock.write() {
+                                            gui_data.bad_checksums += 1;
+                                        }
+                                    }
+                                    Err(NmeaError::NoSentence) => {}
+                                }
+                            }
+
+                            if let Some(mut values) = parser.named_values(&packet.payload) {
+                                // lines without values (e.g. log messages) are only shown in the console
+                                if packet.direction != SerialDirection::Receive || values.is_empty() {
+                                    continue;
+                                }
+                                // without its time the line can not be placed
+                                let Some(time) = time_base.take_named(&mut values, packet.relative_time) else {
+                                    continue;
+                                };
+                                time_base.commit();
+                                parser_settings.scale_named(&mut values);
+                                append_named_values(&mut data, &mut names, &values, time, &packet, &data_lock);
+                                append_derived(&data, &mut derived, &data_lock);
+                                continue;
+                            }
+
+                            if parser_settings.robust {
+                                if packet.direction == SerialDirection::Receive {
+                                    let valid = match parse_fields(&packet.payload, &parser_settings.number_format) {
+                                        // empty line
+                                        Some(values) if values.iter().all(|value| value.is_nan()) => true,
+                                        Some(mut values) => {
+                                            // without its time the line can not be placed
+                                            match time_base.take_indexed(&mut values, packet.relative_time) {
+                                                Some(time) => {
+                                                    time_base.commit();
+                                                    parser_settings.scale_indexed(&mut values);
+                                                    append_values(&mut data, &values, time, &packet, &data_lock, &parser_settings, &cli_column_labels);
+                                                    append_derived(&data, &mut derived, &data_lock);
+                                                    true
+                                                }
+                                                None => false,
+                                            }
+                                        }
+                                        None => false,
+                                    };
+                                    if !valid {
+                                        if let Ok(mut gui_data) = data_lock.write() {
+                                            gui_data.malformed_lines += 1;
+                                        }
+                                    }
+                                }
+                                continue;
+                            }
+
+                            let mut split_data = split(&packet.payload, &parser_settings.number_format);
+                            // without its time the line does not fit, the wrap-around tracking only
+                            // moves on once the line has been appended
+                            let time = time_base.take_indexed(&mut split_data, packet.relative_time);
+                            parser_settings.scale_indexed(&mut split_data);
                             if data.dataset.is_empty() || failed_format_counter > 10 {
                                 // resetting dataset
                                 data.time = vec![];
                                 data.dataset = vec![vec![]; max(split_data.len(), 1)];
                                 if let Ok(mut gui_data) = data_lock.write() {
                                     gui_data.plots = (0..max(split_data.len(), 1))
-                                        .map(|i| (cli_column_labels.get(i).cloned().unwrap_or_else(|| format!("Column {i}")), vec![]))
+                                        .map(|i| (column_label(&cli_column_labels, &parser_settings, i), vec![]))
                                         .collect();
                                 }
+                                recompute_derived(&data, &mut derived, &data_lock);
                                 failed_format_counter = 0;
                                 // log::error!("resetting dataset. split length = {}, length data.dataset = {}", split_data.len(), data.dataset.len());
-                            } else if split_data.len() == data.dataset.len() {
+                            } else if let Some(time) = time.filter(|_| split_data.len() == data.dataset.len()) {
                                 // appending data
                                 for (i, set) in data.dataset.iter_mut().enumerate() {
                                     set.push(split_data[i]);
                                     failed_format_counter = 0;
                                 }
 
-                                data.time.push(packet.relative_time);
+                                data.time.push(time);
                                 data.absolute_time.push(packet.absolute_time);
+                                time_base.commit();
 
                                 // appending data for GUI thread
                                 if let Ok(mut gui_data) = data_lock.write() {
@@ -135,8 +613,8 @@ fn main_thread(
                                         if data.time.len() == data_i.len() {
                                             if let Some(y) = data_i.last() {
                                                 graph.push(PlotPoint {
-                                                    x: packet.relative_time / 1000.0,
-                                                    y: *y as f64,
+                                                    x: time / 1000.0,
+                                                    y: *y,
                                                 });
                                             }
                                         }
@@ -149,9 +627,12 @@ fn main_thread(
                                     if let Ok(mut gui_data) = data_lock.write() {
                                         gui_data.prints = vec!["".to_string(); max(split_data.len(), 1)];
                                         gui_data.plots = (0..max(split_data.len(), 1))
-                                            .map(|i| (format!("Column {i}"), vec![]))
+                                            .map(|i| (column_label(&cli_column_labels, &parser_settings, i), vec![]))
                                             .collect();
                                     }
+                                    recompute_derived(&data, &mut derived, &data_lock);
+                                } else {
+                                    append_derived(&data, &mut derived, &data_lock);
                                 }
                             } else {
                                 // not same length
@@ -160,6 +641,9 @@ fn main_thread(
                             }
                         }
                     }
+                } else {
+                    // the serial thread has exited, the connection was closed
+                    break;
                 }
             }
             recv(gui_cmd_rx) -> msg => {
@@ -167,10 +651,18 @@ fn main_thread(
                     match cmd {
                         GuiCommand::Clear => {
                             data = DataContainer::default();
+                            names.clear();
                             failed_format_counter = 0;
                             if let Ok(mut gui_data) = data_lock.write() {
                                 *gui_data = GuiOutputDataContainer::default();
                             }
+                            recompute_derived(&data, &mut derived, &data_lock);
+                        }
+                        GuiCommand::DerivedChannels(channels) => {
+                            if channels != derived.definitions() {
+                                derived = DerivedChannels::new(&channels);
+                                recompute_derived(&data, &mut derived, &data_lock);
+                            }
                         }
                         GuiCommand::ShowTimestamps(val) => {
                             show_timestamps = val;
@@ -178,7 +670,38 @@ fn main_thread(
                         GuiCommand::ShowSentTraffic(val) => {
                             show_sent_cmds = val;
                         }
+                        GuiCommand::ShowHexDump(val) => {
+                            show_hex_dump = val;
+                            if !file_opened {
+                                // re-render the console from the recorded traffic
+                                let prints = console_prints(&data.raw_traffic, show_timestamps, show_sent_cmds, show_hex_dump, control_chars, pretty_print_json);
+                                if let Ok(mut gui_data) = data_lock.write() {
+                                    gui_data.prints = prints;
+                                }
+                            }
+                        }
+                        GuiCommand::ShowControlChars(val) => {
+                            control_chars = val;
+                            if !file_opened {
+                                let prints = console_prints(&data.raw_traffic, show_timestamps, show_sent_cmds, show_hex_dump, control_chars, pretty_print_json);
+                                if let Ok(mut gui_data) = data_lock.write() {
+                                    gui_data.prints = prints;
+                                }
+                            }
+                        }
+                        GuiCommand::PrettyPrintJson(val) => {
+                            pretty_print_json = val;
+                            if !file_opened {
+                                let prints = console_prints(&data.raw_traffic, show_timestamps, show_sent_cmds, show_hex_dump, control_chars, pretty_print_json);
+                                if let Ok(mut gui_data) = data_lock.write() {
+                                    gui_data.prints = prints;
+                                }
+                            }
+                        }
                     }
+                } else {
+                    // the GUI has closed the connection
+                    break;
                 }
             }
             recv(load_rx) -> msg => {
@@ -211,12 +734,14 @@ fn main_thread(
                                                 for (y,t) in data_i.iter().zip(data.time.iter()) {
                                                         graph.push(PlotPoint {
                                                             x: *t / 1000.0,
-                                                            y: *y as f64 ,
+                                                            y: *y,
                                                         });
                                                 }
                                             }
 
                                         }
+                                        recompute_derived(&data, &mut derived, &data_lock);
+                                        file_options.names.extend(derived.names());
                                         load_names_tx
                                             .send(file_options.names)
                                             .expect("unable to send names on channel after loading");
@@ -242,7 +767,19 @@ fn main_thread(
             }
             recv(save_rx) -> msg => {
                 if let Ok(csv_options) = msg {
-                    match save_to_csv(&data, &csv_options) {
+                    // the derived channels are saved like columns
+                    let columns = data.dataset.len();
+                    data.dataset.extend(derived.dataset.iter().cloned());
+                    let saved = if parser_settings.mode == ParseMode::Tag && !file_opened {
+                        // every channel group has its own samples
+                        let derived_names = derived.names();
+                        let groups: Vec<&str> = names.iter().chain(&derived_names).map(|name| channel_group(name)).collect();
+                        save_groups_to_csv(&data, &csv_options, &groups)
+                    } else {
+                        save_to_csv(&data, &csv_options)
+                    };
+                    data.dataset.truncate(columns);
+                    match saved {
                         Ok(_) => {
                             log::info!("saved data file to {:?} ", csv_options.file_path);
                         }
@@ -292,6 +829,20 @@ fn parse_parity(s: &str) -> Result<serialport::Parity, String> {
     }
 }
 
+fn parse_regex(s: &str) -> Result<Regex, String> {
+    Regex::new(s).map_err(|e| format!("invalid regex {s:?}: {e}"))
+}
+
+fn parse_duration(s: &str) -> Result<Duration, String> {
+    let secs: f64 = s
+        .parse()
+        .map_err(|_e| format!("duration not a number: {s}"))?;
+    if !secs.is_finite() || secs <= 0.0 {
+        return Err(format!("invalid duration: {s}"));
+    }
+    Duration::try_from_secs_f64(secs).map_err(|e| format!("invalid duration {s}: {e}"))
+}
+
 fn parse_color(s: &str) -> Result<egui::Color32, String> {
     Ok(egui::ecolor::HexColor::from_str_without_hash(s)
         .map_err(|e| format!("invalid color {s:?}: {e:?}"))?
@@ -328,6 +879,42 @@ struct CliOptions {
     #[options(short = "F")]
     file: Option<std::path::PathBuf>,
 
+    /// Send a BREAK of the given length in ms after connecting
+    #[options(no_short, long = "break", meta = "MS")]
+    send_break: Option<u64>,
+
+    /// Command to send after connecting, can be specified multiple times
+    #[options(no_short, long = "send", meta = "CMD")]
+    send: Vec<String>,
+
+    /// Line ending appended to the commands of --send (default=\r\n)
+    #[options(no_short, meta = "EOL")]
+    eol: Option<String>,
+
+    /// Record without GUI, the traffic is printed to stdout
+    #[options(no_short)]
+    headless: bool,
+
+    /// Do not print the traffic to stdout (headless)
+    #[options(no_short)]
+    quiet: bool,
+
+    /// Stream the parsed data to a CSV file (headless)
+    #[options(no_short, meta = "FILE")]
+    csv: Option<std::path::PathBuf>,
+
+    /// Stream the raw traffic to a CSV file (headless)
+    #[options(no_short, meta = "FILE")]
+    raw: Option<std::path::PathBuf>,
+
+    /// Stop recording after the given number of seconds (headless)
+    #[options(no_short, meta = "SECS", parse(try_from_str = "parse_duration"))]
+    duration: Option<Duration>,
+
+    /// Stop recording once a received line matches the regex (headless)
+    #[options(no_short, meta = "REGEX", parse(try_from_str = "parse_regex"))]
+    stop_on: Option<Regex>,
+
     /// Column labels, can be specified multiple times for more columns
     #[options(no_short, long = "column")]
     column_labels: Vec<String>,
@@ -340,14 +927,32 @@ struct CliOptions {
 }
 
 fn main() {
-    egui_logger::builder().init().unwrap();
-
     let args = CliOptions::parse_args_default_or_exit();
 
+    if args.headless {
+        init_logger();
+    } else {
+        egui_logger::builder().init().unwrap();
+    }
+
     let gui_settings = load_gui_settings();
     let saved_serial_device_configs = load_serial_settings();
 
     let mut device = Device::default();
+    // without GUI the saved settings of the device apply, e.g. its framing and parse mode
+    let saved_device_idx = args
+        .device
+        .as_ref()
+        .filter(|_| args.headless)
+        .and_then(|name| {
+            saved_serial_device_configs
+                .devices
+                .iter()
+                .position(|saved| saved.name == *name)
+        });
+    if let Some(idx) = saved_device_idx {
+        device = saved_serial_device_configs.devices[idx].clone();
+    }
     if let Some(name) = args.device {
         device.name = name;
     }
@@ -367,21 +972,51 @@ fn main() {
         device.parity = parity;
     }
 
-    let device_lock = Arc::new(RwLock::new(device));
-    let devices_lock = Arc::new(RwLock::new(vec![gui_settings.device.clone()]));
-    let data_lock = Arc::new(RwLock::new(GuiOutputDataContainer::default()));
-    let connected_lock = Arc::new(RwLock::new(false));
-
-    let (save_tx, save_rx): (Sender<FileOptions>, Receiver<FileOptions>) =
-        crossbeam_channel::unbounded();
-    let (load_tx, load_rx): (Sender<PathBuf>, Receiver<PathBuf>) = crossbeam_channel::unbounded();
-    let (loaded_names_tx, loaded_names_rx): (Sender<Vec<String>>, Receiver<Vec<String>>) =
-        crossbeam_channel::unbounded();
-    let (send_tx, send_rx): (Sender<String>, Receiver<String>) = crossbeam_channel::unbounded();
-    let (gui_cmd_tx, gui_cmd_rx): (Sender<GuiCommand>, Receiver<GuiCommand>) =
-        crossbeam_channel::unbounded();
-    let (raw_data_tx, raw_data_rx): (Sender<Packet>, Receiver<Packet>) =
-        crossbeam_channel::unbounded();
+    let eol = args
+        .eol
+        .unwrap_or_else(|| "\\r\\n".to_string())
+        .replace("\\r", "\r")
+        .replace("\\n", "\n");
+    let commands: Vec<String> = args.send.iter().map(|cmd| cmd.clone() + &eol).collect();
+
+    if args.headless {
+        if device.name.is_empty() {
+            eprintln!("--headless requires a device");
+            std::process::exit(2);
+        }
+        let options = HeadlessOptions {
+            print: !args.quiet,
+            csv_file: args.csv,
+            raw_file: args.raw,
+            duration: args.duration,
+            stop_on: args.stop_on,
+            commands,
+            serial_commands: args
+                .send_break
+                .map(|ms| SerialCommand::Break(Duration::from_millis(ms)))
+                .into_iter()
+                .collect(),
+            column_labels: match saved_device_idx {
+                Some(idx) if args.column_labels.is_empty() => saved_serial_device_configs
+                    .labels
+                    .get(idx)
+                    .cloned()
+                    .unwrap_or_default(),
+                _ => args.column_labels,
+            },
+        };
+        if let Err(err) = run_headless(device, options) {
+            log::error!("{err}");
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    let t_zero = Instant::now();
+    let devices_lock = Arc::new(RwLock::new(vec![PortInfo {
+        name: gui_settings.device.clone(),
+        usb: None,
+    }]));
     let (sync_tx, sync_rx): (Sender<bool>, Receiver<bool>) = crossbeam_channel::unbounded();
 
     let serial_2_devices_lock = devices_lock.clone();
@@ -390,37 +1025,31 @@ fn main() {
         serial_devices_thread(serial_2_devices_lock);
     });
 
-    let serial_device_lock = device_lock.clone();
-    let serial_devices_lock = devices_lock.clone();
-    let serial_connected_lock = connected_lock.clone();
-
-    let _serial_thread_handler = thread::spawn(|| {
-        serial_thread(
-            send_rx,
-            raw_data_tx,
-            serial_device_lock,
-            serial_devices_lock,
-            serial_connected_lock,
-        );
-    });
+    let connection = start_connection(
+        device,
+        t_zero,
+        devices_lock.clone(),
+        sync_tx.clone(),
+        args.column_labels,
+    );
 
-    let main_data_lock = data_lock.clone();
-
-    let _main_thread_handler = thread::spawn(|| {
-        main_thread(
-            sync_tx,
-            main_data_lock,
-            raw_data_rx,
-            save_rx,
-            load_rx,
-            loaded_names_tx,
-            gui_cmd_rx,
-            args.column_labels,
-        );
-    });
+    if let Some(break_ms) = args.send_break {
+        // executed by the serial thread once the port is open
+        connection
+            .serial_cmd_tx
+            .send(SerialCommand::Break(Duration::from_millis(break_ms)))
+            .expect("failed to send break");
+    }
+
+    for command in commands {
+        connection
+            .send_tx
+            .send(command)
+            .expect("failed to send command");
+    }
 
     if let Some(file) = args.file {
-        load_tx.send(file).expect("failed to send file");
+        connection.load_tx.send(file).expect("failed to send file");
     }
 
     let options = eframe::NativeOptions {
@@ -434,10 +1063,7 @@ fn main() {
         ..Default::default()
     };
 
-    let gui_data_lock = data_lock;
-    let gui_device_lock = device_lock;
     let gui_devices_lock = devices_lock;
-    let gui_connected_lock = connected_lock;
 
     if let Err(e) = eframe::run_native(
         "Serial Monitor",
@@ -458,18 +1084,13 @@ fn main() {
 
             Ok(Box::new(MyApp::new(
                 ctx,
-                gui_data_lock,
-                gui_device_lock,
+                connection,
                 gui_devices_lock,
                 saved_serial_device_configs,
-                gui_connected_lock,
                 gui_settings,
-                save_tx,
-                load_tx,
-                loaded_names_rx,
-                send_tx,
-                gui_cmd_tx,
                 args.column_colors,
+                t_zero,
+                sync_tx,
             )))
         }),
     ) {
@@ -0,0 +1,442 @@
+use serialport::{ClearBuffer, DataBits, ErrorKind, FlowControl, Parity, SerialPort, StopBits};
+use std::io::{Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+use crate::serial::Device;
+
+const TCP_PREFIX: &str = "tcp://";
+const RFC2217_PREFIX: &str = "rfc2217://";
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(2);
+
+// telnet
+const IAC: u8 = 255;
+const DONT: u8 = 254;
+const DO: u8 = 253;
+const WONT: u8 = 252;
+const WILL: u8 = 251;
+const SB: u8 = 250;
+const SE: u8 = 240;
+const BINARY: u8 = 0;
+const SGA: u8 = 3;
+
+// RFC 2217
+const COM_PORT_OPTION: u8 = 44;
+const SET_BAUDRATE: u8 = 1;
+const SET_DATASIZE: u8 = 2;
+const SET_PARITY: u8 = 3;
+const SET_STOPSIZE: u8 = 4;
+const SET_CONTROL: u8 = 5;
+const SET_MODEMSTATE_MASK: u8 = 11;
+const PURGE_DATA: u8 = 12;
+const SERVER_OFFSET: u8 = 100;
+const NOTIFY_MODEMSTATE: u8 = 7;
+
+const CONTROL_NO_FLOW: u8 = 1;
+const CONTROL_XON_XOFF: u8 = 2;
+const CONTROL_HARDWARE: u8 = 3;
+const CONTROL_BREAK_ON: u8 = 5;
+const CONTROL_BREAK_OFF: u8 = 6;
+const CONTROL_DTR_ON: u8 = 8;
+const CONTROL_DTR_OFF: u8 = 9;
+const CONTROL_RTS_ON: u8 = 11;
+const CONTROL_RTS_OFF: u8 = 12;
+
+const MODEM_CTS: u8 = 0x10;
+const MODEM_DSR: u8 = 0x20;
+const MODEM_RI: u8 = 0x40;
+const MODEM_CD: u8 = 0x80;
+
+/// Returns true for device names like `tcp://host:port` or `rfc2217://host:port`.
+pub fn is_network_device(name: &str) -> bool {
+    name.starts_with(TCP_PREFIX) || name.starts_with(RFC2217_PREFIX)
+}
+
+/// Connects to a network serial server and applies the line settings of the device
+/// (only transmitted to the remote end for RFC 2217).
+pub fn open_network_port(
+    device: &Device,
+    timeout: Duration,
+) -> Result<Box<dyn SerialPort>, serialport::Error> {
+    let (address, rfc2217) = if let Some(address) = device.name.strip_prefix(RFC2217_PREFIX) {
+        (address, true)
+    } else if let Some(address) = device.name.strip_prefix(TCP_PREFIX) {
+        (address, false)
+    } else {
+        return Err(serialport::Error::new(
+            ErrorKind::InvalidInput,
+            format!("not a network device: {}", device.name),
+        ));
+    };
+    let socket_address = address
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| serialport::Error::new(ErrorKind::NoDevice, "unable to resolve address"))?;
+    let stream = TcpStream::connect_timeout(&socket_address, CONNECT_TIMEOUT)?;
+    stream.set_nodelay(true)?;
+
+    let mut port = NetworkPort {
+        name: device.name.clone(),
+        stream,
+        rfc2217,
+        telnet: TelnetState::Data,
+        modem_state: 0,
+        baud_rate: device.baud_rate,
+        data_bits: device.data_bits,
+        flow_control: device.flow_control,
+        parity: device.parity,
+        stop_bits: device.stop_bits,
+        timeout,
+    };
+    port.set_timeout(timeout)?;
+    if rfc2217 {
+        port.send_raw(&[
+            IAC,
+            WILL,
+            BINARY,
+            IAC,
+            DO,
+            BINARY,
+            IAC,
+            WILL,
+            SGA,
+            IAC,
+            DO,
+            SGA,
+            IAC,
+            WILL,
+            COM_PORT_OPTION,
+        ])?;
+        port.set_baud_rate(device.baud_rate)?;
+        port.set_data_bits(device.data_bits)?;
+        port.set_parity(device.parity)?;
+        port.set_stop_bits(device.stop_bits)?;
+        port.set_flow_control(device.flow_control)?;
+        port.com_port_command(SET_MODEMSTATE_MASK, &[0xFF])?;
+    }
+    Ok(Box::new(port))
+}
+
+#[derive(Debug, Clone)]
+enum TelnetState {
+    Data,
+    Iac,
+    Negotiate(u8),
+    Sub(Vec<u8>),
+    SubIac(Vec<u8>),
+}
+
+/// A raw TCP or RFC 2217 (telnet COM port control) connection that behaves like a serial port.
+#[derive(Debug)]
+struct NetworkPort {
+    name: String,
+    stream: TcpStream,
+    rfc2217: bool,
+    telnet: TelnetState,
+    modem_state: u8,
+    baud_rate: u32,
+    data_bits: DataBits,
+    flow_control: FlowControl,
+    parity: Parity,
+    stop_bits: StopBits,
+    timeout: Duration,
+}
+
+impl NetworkPort {
+    fn send_raw(&self, bytes: &[u8]) -> std::io::Result<()> {
+        (&self.stream).write_all(bytes)
+    }
+
+    fn com_port_command(&self, command: u8, value: &[u8]) -> serialport::Result<()> {
+        if !self.rfc2217 {
+            return Ok(());
+        }
+        let mut bytes = vec![IAC, SB, COM_PORT_OPTION, command];
+        for &b in value {
+            bytes.push(b);
+            if b == IAC {
+                bytes.push(IAC);
+            }
+        }
+        bytes.extend_from_slice(&[IAC, SE]);
+        Ok(self.send_raw(&bytes)?)
+    }
+
+    fn modem_line(&self, mask: u8) -> serialport::Result<bool> {
+        if self.rfc2217 {
+            Ok(self.modem_state & mask != 0)
+        } else {
+            Err(serialport::Error::new(
+                ErrorKind::Unknown,
+                "modem lines are not available on raw TCP connections",
+            ))
+        }
+    }
+
+    /// Strips the telnet protocol from the received bytes, answers option negotiations
+    /// and keeps track of the modem state reported by the server.
+    fn decode_telnet(&mut self, input: &[u8], output: &mut [u8]) -> std::io::Result<usize> {
+        let mut n = 0;
+        let mut replies = vec![];
+        for &byte in input {
+            self.telnet = match std::mem::replace(&mut self.telnet, TelnetState::Data) {
+                TelnetState::Data if byte == IAC => TelnetState::Iac,
+                TelnetState::Data => {
+                    output[n] = byte;
+                    n += 1;
+                    TelnetState::Data
+                }
+                TelnetState::Iac => match byte {
+                    IAC => {
+                        output[n] = IAC;
+                        n += 1;
+                        TelnetState::Data
+                    }
+                    DO | DONT | WILL | WONT => TelnetState::Negotiate(byte),
+                    SB => TelnetState::Sub(vec![]),
+                    _ => TelnetState::Data,
+                },
+                TelnetState::Negotiate(command) => {
+                    let supported = matches!(byte, BINARY | SGA | COM_PORT_OPTION);
+                    match command {
+                        DO if !supported => replies.extend_from_slice(&[IAC, WONT, byte]),
+                        WILL if !supported => replies.extend_from_slice(&[IAC, DONT, byte]),
+                        _ => {}
+                    }
+                    TelnetState::Data
+                }
+                TelnetState::Sub(sub) if byte == IAC => TelnetState::SubIac(sub),
+                TelnetState::Sub(mut sub) => {
+                    sub.push(byte);
+                    TelnetState::Sub(sub)
+                }
+                TelnetState::SubIac(mut sub) => match byte {
+                    SE => {
+                        if let [COM_PORT_OPTION, command, state, ..] = sub[..] {
+                            if command == NOTIFY_MODEMSTATE + SERVER_OFFSET {
+                                self.modem_state = state;
+                            }
+                        }
+                        TelnetState::Data
+                    }
+                    _ => {
+                        sub.push(byte);
+                        TelnetState::Sub(sub)
+                    }
+                },
+            };
+        }
+        if !replies.is_empty() {
+            self.send_raw(&replies)?;
+        }
+        Ok(n)
+    }
+}
+
+impl Read for NetworkPort {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        loop {
+            let mut received = vec![0; buf.len()];
+            let n = match self.stream.read(&mut received) {
+                Ok(0) => {
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::ConnectionAborted,
+                        "connection closed by remote host",
+                    ))
+                }
+                Ok(n) => n,
+                // the serial thread expects a timeout when there is no data
+                Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {
+                    return Err(std::io::ErrorKind::TimedOut.into())
+                }
+                Err(e) => return Err(e),
+            };
+            if !self.rfc2217 {
+                buf[..n].copy_from_slice(&received[..n]);
+                return Ok(n);
+            }
+            // telnet commands only, keep waiting for data
+            let n = self.decode_telnet(&received[..n], buf)?;
+            if n > 0 {
+                return Ok(n);
+            }
+        }
+    }
+}
+
+impl Write for NetworkPort {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        if self.rfc2217 && buf.contains(&IAC) {
+            let mut escaped = Vec::with_capacity(buf.len() + 1);
+            for &b in buf {
+                escaped.push(b);
+                if b == IAC {
+                    escaped.push(IAC);
+                }
+            }
+            self.stream.write_all(&escaped)?;
+            Ok(buf.len())
+        } else {
+            self.stream.write(buf)
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.stream.flush()
+    }
+}
+
+impl SerialPort for NetworkPort {
+    fn name(&self) -> Option<String> {
+        Some(self.name.clone())
+    }
+
+    fn baud_rate(&self) -> serialport::Result<u32> {
+        Ok(self.baud_rate)
+    }
+
+    fn data_bits(&self) -> serialport::Result<DataBits> {
+        Ok(self.data_bits)
+    }
+
+    fn flow_control(&self) -> serialport::Result<FlowControl> {
+        Ok(self.flow_control)
+    }
+
+    fn parity(&self) -> serialport::Result<Parity> {
+        Ok(self.parity)
+    }
+
+    fn stop_bits(&self) -> serialport::Result<StopBits> {
+        Ok(self.stop_bits)
+    }
+
+    fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    fn set_baud_rate(&mut self, baud_rate: u32) -> serialport::Result<()> {
+        self.baud_rate = baud_rate;
+        self.com_port_command(SET_BAUDRATE, &baud_rate.to_be_bytes())
+    }
+
+    fn set_data_bits(&mut self, data_bits: DataBits) -> serialport::Result<()> {
+        self.data_bits = data_bits;
+        self.com_port_command(SET_DATASIZE, &[u8::from(data_bits)])
+    }
+
+    fn set_flow_control(&mut self, flow_control: FlowControl) -> serialport::Result<()> {
+        self.flow_control = flow_control;
+        let control = match flow_control {
+            FlowControl::None => CONTROL_NO_FLOW,
+            FlowControl::Software => CONTROL_XON_XOFF,
+            FlowControl::Hardware => CONTROL_HARDWARE,
+        };
+        self.com_port_command(SET_CONTROL, &[control])
+    }
+
+    fn set_parity(&mut self, parity: Parity) -> serialport::Result<()> {
+        self.parity = parity;
+        let value = match parity {
+            Parity::None => 1,
+            Parity::Odd => 2,
+            Parity::Even => 3,
+        };
+        self.com_port_command(SET_PARITY, &[value])
+    }
+
+    fn set_stop_bits(&mut self, stop_bits: StopBits) -> serialport::Result<()> {
+        self.stop_bits = stop_bits;
+        let value = match stop_bits {
+            StopBits::One => 1,
+            StopBits::Two => 2,
+        };
+        self.com_port_command(SET_STOPSIZE, &[value])
+    }
+
+    fn set_timeout(&mut self, timeout: Duration) -> serialport::Result<()> {
+        self.timeout = timeout;
+        // a zero duration is not allowed as socket timeout
+        self.stream
+            .set_read_timeout(Some(timeout.max(Duration::from_millis(1))))?;
+        Ok(())
+    }
+
+    fn write_request_to_send(&mut self, level: bool) -> serialport::Result<()> {
+        let control = if level {
+            CONTROL_RTS_ON
+        } else {
+            CONTROL_RTS_OFF
+        };
+        self.com_port_command(SET_CONTROL, &[control])
+    }
+
+    fn write_data_terminal_ready(&mut self, level: bool) -> serialport::Result<()> {
+        let control = if level {
+            CONTROL_DTR_ON
+        } else {
+            CONTROL_DTR_OFF
+        };
+        self.com_port_command(SET_CONTROL, &[control])
+    }
+
+    fn read_clear_to_send(&mut self) -> serialport::Result<bool> {
+        self.modem_line(MODEM_CTS)
+    }
+
+    fn read_data_set_ready(&mut self) -> serialport::Result<bool> {
+        self.modem_line(MODEM_DSR)
+    }
+
+    fn read_ring_indicator(&mut self) -> serialport::Result<bool> {
+        self.modem_line(MODEM_RI)
+    }
+
+    fn read_carrier_detect(&mut self) -> serialport::Result<bool> {
+        self.modem_line(MODEM_CD)
+    }
+
+    fn bytes_to_read(&self) -> serialport::Result<u32> {
+        Ok(0)
+    }
+
+    fn bytes_to_write(&self) -> serialport::Result<u32> {
+        Ok(0)
+    }
+
+    fn clear(&self, buffer_to_clear: ClearBuffer) -> serialport::Result<()> {
+        let value = match buffer_to_clear {
+            ClearBuffer::Input => 1,
+            ClearBuffer::Output => 2,
+            ClearBuffer::All => 3,
+        };
+        self.com_port_command(PURGE_DATA, &[value])
+    }
+
+    fn try_clone(&self) -> serialport::Result<Box<dyn SerialPort>> {
+        Ok(Box::new(NetworkPort {
+            name: self.name.clone(),
+            stream: self.stream.try_clone()?,
+            rfc2217: self.rfc2217,
+            telnet: self.telnet.clone(),
+            modem_state: self.modem_state,
+            baud_rate: self.baud_rate,
+            data_bits: self.data_bits,
+            flow_control: self.flow_control,
+            parity: self.parity,
+            stop_bits: self.stop_bits,
+            timeout: self.timeout,
+        }))
+    }
+
+    fn set_break(&self) -> serialport::Result<()> {
+        self.com_port_command(SET_CONTROL, &[CONTROL_BREAK_ON])
+    }
+
+    fn clear_break(&self) -> serialport::Result<()> {
+        self.com_port_command(SET_CONTROL, &[CONTROL_BREAK_OFF])
+    }
+}
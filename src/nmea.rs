@@ -0,0 +1,272 @@
+use std::fmt;
+use std::ops::Range;
+
+/// A NMEA 0183 sentence like `$GPGGA,123519,4807.038,N,...*47` with a valid checksum.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NmeaSentence {
+    /// Talker of the sentence, e.g. `GP` for GPS or `GN` for multiple constellations.
+    pub talker: String,
+    /// Type of the sentence, e.g. `GGA`.
+    pub kind: String,
+    /// The fields after the address, empty fields are empty strings.
+    pub fields: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum NmeaError {
+    /// The line does not start with `$` and a five character address.
+    NoSentence,
+    Checksum {
+        expected: u8,
+        received: u8,
+    },
+}
+
+impl fmt::Display for NmeaError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            NmeaError::NoSentence => write!(f, "no NMEA sentence"),
+            NmeaError::Checksum { expected, received } => write!(
+                f,
+                "checksum mismatch, expected *{expected:02X}, received *{received:02X}"
+            ),
+        }
+    }
+}
+
+/// XOR of all characters between `$` and `*`.
+fn checksum(body: &str) -> u8 {
+    body.bytes().fold(0, |sum, b| sum ^ b)
+}
+
+/// Parses a sentence and verifies its checksum, sentences without checksum are accepted.
+pub fn parse_sentence(line: &str) -> Result<NmeaSentence, NmeaError> {
+    let line = line.trim();
+    let body = line.strip_prefix('$').ok_or(NmeaError::NoSentence)?;
+    let body = match body.rsplit_once('*') {
+        Some((body, received)) => {
+            let received = u8::from_str_radix(received, 16).map_err(|_| NmeaError::NoSentence)?;
+            let expected = checksum(body);
+            if received != expected {
+                return Err(NmeaError::Checksum { expected, received });
+            }
+            body
+        }
+        None => body,
+    };
+    let mut fields = body.split(',');
+    let address = fields.next().unwrap_or_default();
+    if address.len() != 5 || !address.chars().all(|c| c.is_ascii_alphanumeric()) {
+        return Err(NmeaError::NoSentence);
+    }
+    Ok(NmeaSentence {
+        talker: address[..2].to_string(),
+        kind: address[2..].to_string(),
+        fields: fields.map(str::to_string).collect(),
+    })
+}
+
+/// Decodes a sentence into named values, sentences that are not supported have none.
+pub fn parse_nmea(line: &str) -> Result<Vec<(String, f64)>, NmeaError> {
+    parse_sentence(line).map(|sentence| sentence.values())
+}
+
+/// Ranges of the sentences in `text` whose checksum does not match, e.g. to highlight them.
+/// `text` is the console text: a sentence ends before its line ending, also if the control
+/// characters are shown as names (`<CR>`), escapes (`\r`) or pictures (`␍`).
+pub fn invalid_sentences(text: &str) -> Vec<Range<usize>> {
+    let mut ranges = vec![];
+    let mut pos = 0;
+    for line in text.split_inclusive('\n') {
+        if let Some(start) = line.find('$') {
+            let rest = &line[start..];
+            let end = rest
+                .find(|c: char| !(c.is_ascii_graphic() || c == ' ') || c == '<' || c == '\\')
+                .unwrap_or(rest.len());
+            let sentence = rest[..end].trim_end();
+            if let Err(NmeaError::Checksum { .. }) = parse_sentence(sentence) {
+                ranges.push(pos + start..pos + start + sentence.len());
+            }
+        }
+        pos += line.len();
+    }
+    ranges
+}
+
+impl NmeaSentence {
+    fn field(&self, i: usize) -> Option<f64> {
+        self.fields.get(i)?.trim().parse().ok()
+    }
+
+    fn flag(&self, i: usize) -> &str {
+        self.fields.get(i).map_or("", |field| field.trim())
+    }
+
+    /// Latitude or longitude in degrees from `ddmm.mmmm` and the hemisphere after it.
+    fn coordinate(&self, i: usize) -> Option<f64> {
+        let value = self.field(i)?;
+        let degrees = (value / 100.0).trunc();
+        let degrees = degrees + (value - degrees * 100.0) / 60.0;
+        match self.flag(i + 1) {
+            "S" | "W" => Some(-degrees),
+            _ => Some(degrees),
+        }
+    }
+
+    /// UTC time of day in s from `hhmmss.ss`.
+    fn utc(&self, i: usize) -> Option<f64> {
+        let value = self.field(i)?;
+        let hours = (value / 10_000.0).trunc();
+        let minutes = (value / 100.0).trunc() - hours * 100.0;
+        let seconds = value - hours * 10_000.0 - minutes * 100.0;
+        Some(hours * 3600.0 + minutes * 60.0 + seconds)
+    }
+
+    /// The channels of GGA, RMC, VTG, GSA and GSV sentences, empty fields are left out.
+    pub fn values(&self) -> Vec<(String, f64)> {
+        let values = match self.kind.as_str() {
+            "GGA" => vec![
+                ("utc", self.utc(0)),
+                ("lat", self.coordinate(1)),
+                ("lon", self.coordinate(3)),
+                ("fix_quality", self.field(5)),
+                ("satellites", self.field(6)),
+                ("hdop", self.field(7)),
+                ("alt", self.field(8)),
+            ],
+            // only valid fixes have a position
+            "RMC" if self.flag(1) == "A" => vec![
+                ("utc", self.utc(0)),
+                ("lat", self.coordinate(2)),
+                ("lon", self.coordinate(4)),
+                ("speed", self.field(6).map(|knots| knots * 1.852)),
+                ("course", self.field(7)),
+            ],
+            "VTG" => vec![
+                ("course", self.field(0)),
+                (
+                    "speed",
+                    self.field(6)
+                        .or_else(|| self.field(4).map(|knots| knots * 1.852)),
+                ),
+            ],
+            "GSA" => vec![
+                ("fix_type", self.field(1)),
+                ("pdop", self.field(14)),
+                ("hdop", self.field(15)),
+                ("vdop", self.field(16)),
+            ],
+            "GSV" => {
+                return self
+                    .field(2)
+                    .map(|satellites| {
+                        vec![(format!("satellites_in_view_{}", self.talker), satellites)]
+                    })
+                    .unwrap_or_default();
+            }
+            _ => vec![],
+        };
+        values
+            .into_iter()
+            .filter_map(|(name, value)| Some((name.to_string(), value?)))
+            .collect()
+    }
+}
+
+/// A satellite of a GSV sentence.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Satellite {
+    pub talker: String,
+    pub prn: u32,
+    /// Elevation in degrees.
+    pub elevation: Option<f64>,
+    /// Azimuth in degrees.
+    pub azimuth: Option<f64>,
+    /// Signal to noise ratio in dB, `None` if the satellite is not tracked.
+    pub snr: Option<f64>,
+}
+
+/// Fix and satellites of the latest sentences.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct NmeaStatus {
+    /// GGA fix quality: 0 invalid, 1 GPS, 2 DGPS, 4 RTK fixed, 5 RTK float, ...
+    pub fix_quality: Option<u8>,
+    /// GSA fix type: 1 no fix, 2 2D, 3 3D.
+    pub fix_type: Option<u8>,
+    pub satellites_used: Option<u32>,
+    pub hdop: Option<f64>,
+    pub lat: Option<f64>,
+    pub lon: Option<f64>,
+    pub alt: Option<f64>,
+    /// Speed over ground in km/h.
+    pub speed: Option<f64>,
+    pub satellites: Vec<Satellite>,
+}
+
+impl NmeaStatus {
+    pub fn update(&mut self, sentence: &NmeaSentence) {
+        let values = sentence.values();
+        let value = |name: &str| {
+            values
+                .iter()
+                .find(|(value_name, _)| value_name == name)
+                .map(|(_, value)| *value)
+        };
+        match sentence.kind.as_str() {
+            "GGA" => {
+                self.fix_quality = value("fix_quality").map(|quality| quality as u8);
+                self.satellites_used = value("satellites").map(|satellites| satellites as u32);
+                self.hdop = value("hdop");
+                self.lat = value("lat");
+                self.lon = value("lon");
+                self.alt = value("alt");
+            }
+            "RMC" | "VTG" => {
+                if let Some(speed) = value("speed") {
+                    self.speed = Some(speed);
+                }
+            }
+            "GSA" => self.fix_type = value("fix_type").map(|fix_type| fix_type as u8),
+            "GSV" => {
+                // the first message of a cycle starts a new list of the talker
+                if sentence.field(1) == Some(1.0) {
+                    self.satellites
+                        .retain(|satellite| satellite.talker != sentence.talker);
+                }
+                // four fields per satellite, an incomplete group at the end is ignored
+                let groups = sentence.fields.len().saturating_sub(3) / 4;
+                for i in (3..3 + groups * 4).step_by(4) {
+                    let Some(prn) = sentence.field(i) else {
+                        continue;
+                    };
+                    self.satellites.push(Satellite {
+                        talker: sentence.talker.clone(),
+                        prn: prn as u32,
+                        elevation: sentence.field(i + 1),
+                        azimuth: sentence.field(i + 2),
+                        snr: sentence.field(i + 3),
+                    });
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Text of the fix, e.g. `3D (DGPS)`.
+    pub fn fix(&self) -> String {
+        let quality = match self.fix_quality {
+            Some(0) | None => return "No Fix".to_string(),
+            Some(1) => "GPS",
+            Some(2) => "DGPS",
+            Some(4) => "RTK fixed",
+            Some(5) => "RTK float",
+            Some(6) => "estimated",
+            Some(_) => "other",
+        };
+        match self.fix_type {
+            Some(2) => format!("2D ({quality})"),
+            Some(3) => format!("3D ({quality})"),
+            _ => quality.to_string(),
+        }
+    }
+}
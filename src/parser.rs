@@ -0,0 +1,505 @@
+use crate::nmea::parse_nmea;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fmt;
+use std::sync::OnceLock;
+
+/// How the values of a received line are found.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub enum ParseMode {
+    /// All numbers, separated by `,` or `:`, one column per position.
+    #[default]
+    Numbers,
+    /// `name=value` or `name:value` pairs, one column per name.
+    KeyValue,
+    /// Named capture groups of the [`ParserSettings::patterns`], one column per group name.
+    Regex,
+    /// One JSON object per line, nested numeric fields are named with dots, e.g. `imu.ax`.
+    Json,
+    /// The first field is a tag that selects a channel group, e.g. `IMU,0.1,0.2` gives the
+    /// channels `IMU.0` and `IMU.1`.
+    Tag,
+    /// NMEA 0183 sentences of GNSS receivers, e.g. `$GPGGA,...*47` gives `lat`, `lon`, `alt`, ...
+    Nmea,
+}
+
+impl ParseMode {
+    pub const ALL: [ParseMode; 6] = [
+        ParseMode::Numbers,
+        ParseMode::KeyValue,
+        ParseMode::Regex,
+        ParseMode::Json,
+        ParseMode::Tag,
+        ParseMode::Nmea,
+    ];
+}
+
+impl fmt::Display for ParseMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ParseMode::Numbers => write!(f, "Numbers"),
+            ParseMode::KeyValue => write!(f, "Key=Value"),
+            ParseMode::Regex => write!(f, "Regex"),
+            ParseMode::Json => write!(f, "JSON"),
+            ParseMode::Tag => write!(f, "Tag"),
+            ParseMode::Nmea => write!(f, "NMEA"),
+        }
+    }
+}
+
+/// Unit of a device time column.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub enum TimeUnit {
+    Micros,
+    #[default]
+    Millis,
+    Seconds,
+    /// Counter ticks at the given frequency in Hz.
+    Ticks(f64),
+}
+
+impl TimeUnit {
+    pub const ALL: [TimeUnit; 4] = [
+        TimeUnit::Micros,
+        TimeUnit::Millis,
+        TimeUnit::Seconds,
+        TimeUnit::Ticks(1000.0),
+    ];
+
+    /// Returns true if both units are the same, ignoring the tick frequency.
+    pub fn same_unit(&self, other: &TimeUnit) -> bool {
+        std::mem::discriminant(self) == std::mem::discriminant(other)
+    }
+
+    fn to_ms(self, time: f64) -> f64 {
+        match self {
+            TimeUnit::Micros => time / 1000.0,
+            TimeUnit::Millis => time,
+            TimeUnit::Seconds => time * 1000.0,
+            TimeUnit::Ticks(frequency) => time * 1000.0 / frequency,
+        }
+    }
+}
+
+impl fmt::Display for TimeUnit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TimeUnit::Micros => write!(f, "µs"),
+            TimeUnit::Millis => write!(f, "ms"),
+            TimeUnit::Seconds => write!(f, "s"),
+            TimeUnit::Ticks(_) => write!(f, "Ticks"),
+        }
+    }
+}
+
+/// Parser settings of a device.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct ParserSettings {
+    pub mode: ParseMode,
+    /// Regexes of the regex mode, e.g. `\[IMU\] ax=(?P<ax>\S+) ay=(?P<ay>\S+)`.
+    #[serde(default)]
+    pub patterns: Vec<String>,
+    /// Column with the device time, used instead of the time of arrival: its name, or its
+    /// index in numbers mode. Empty to use the time of arrival.
+    #[serde(default)]
+    pub x_field: String,
+    #[serde(default)]
+    pub x_unit: TimeUnit,
+    /// The device time is a 32 bit counter that wraps around.
+    #[serde(default)]
+    pub x_wrap: bool,
+    /// Numbers mode: keep the series when the number of values changes instead of resetting.
+    #[serde(default)]
+    pub robust: bool,
+    /// Numbers and tag mode.
+    #[serde(default)]
+    pub number_format: NumberFormat,
+    #[serde(default)]
+    pub scales: Vec<ColumnScale>,
+}
+
+impl ParserSettings {
+    /// Returns the scaling of a column, by name or by index in numbers mode.
+    pub fn scale(&self, column: &str) -> Option<&ColumnScale> {
+        self.scales.iter().find(|scale| scale.column == column)
+    }
+
+    /// Index of the field a column of the numbers mode is read from. The time column is taken
+    /// out of the values, so the columns after it come from the next field.
+    pub fn field_index(&self, column: usize) -> usize {
+        match self.x_field.trim().parse::<usize>() {
+            Ok(time_field) if column >= time_field => column + 1,
+            _ => column,
+        }
+    }
+
+    /// Returns the settings without the column scaling, which applies to new samples and
+    /// does not require to reset the data.
+    pub fn without_scales(&self) -> ParserSettings {
+        ParserSettings {
+            scales: vec![],
+            ..self.clone()
+        }
+    }
+
+    pub fn scale_named(&self, values: &mut [(String, f64)]) {
+        for (name, value) in values {
+            if let Some(scale) = self.scale(name) {
+                *value = scale.apply(*value);
+            }
+        }
+    }
+
+    /// Scales the values of the numbers mode, the columns are keyed by their field index.
+    pub fn scale_indexed(&self, values: &mut [f64]) {
+        if self.scales.is_empty() {
+            return;
+        }
+        for (i, value) in values.iter_mut().enumerate() {
+            if let Some(scale) = self.scale(&self.field_index(i).to_string()) {
+                *value = scale.apply(*value);
+            }
+        }
+    }
+}
+
+/// How the numbers of the numbers and tag mode are written.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub struct NumberFormat {
+    /// Also accept `0x1F3A`, `0b1011` and `0o17` integer literals.
+    pub radix_literals: bool,
+    /// `3,14` instead of `3.14`, fields are then separated by `;`, `:` or tabs.
+    pub decimal_comma: bool,
+}
+
+impl NumberFormat {
+    pub fn separators(&self) -> &'static [char] {
+        if self.decimal_comma {
+            &[';', ':', '\t']
+        } else {
+            &[',', ':']
+        }
+    }
+
+    /// Parses a (trimmed) field, `None` if it is no number.
+    pub fn parse(&self, field: &str) -> Option<f64> {
+        if self.radix_literals {
+            let (negative, literal) = match field.strip_prefix('-') {
+                Some(literal) => (true, literal),
+                None => (false, field.strip_prefix('+').unwrap_or(field)),
+            };
+            let radix = match literal.get(..2) {
+                Some("0x" | "0X") => Some(16),
+                Some("0b" | "0B") => Some(2),
+                Some("0o" | "0O") => Some(8),
+                _ => None,
+            };
+            if let Some(radix) = radix {
+                let digits = literal[2..].replace('_', "");
+                let value = u64::from_str_radix(&digits, radix).ok()? as f64;
+                return Some(if negative { -value } else { value });
+            }
+        }
+        if self.decimal_comma {
+            field.replace(',', ".").parse().ok()
+        } else {
+            field.parse().ok()
+        }
+    }
+}
+
+/// Scaling of a column from raw values (e.g. ADC counts) to physical values.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ColumnScale {
+    /// Name of the column, or the index of its field in numbers mode (counting the time column).
+    pub column: String,
+    pub gain: f64,
+    pub offset: f64,
+    pub unit: String,
+}
+
+impl ColumnScale {
+    pub fn new(column: String) -> Self {
+        ColumnScale {
+            column,
+            gain: 1.0,
+            offset: 0.0,
+            unit: String::new(),
+        }
+    }
+
+    pub fn apply(&self, raw: f64) -> f64 {
+        raw * self.gain + self.offset
+    }
+}
+
+/// The parser of a device, built from its [`ParserSettings`].
+pub struct Parser {
+    mode: ParseMode,
+    patterns: Vec<Regex>,
+    number_format: NumberFormat,
+}
+
+impl Parser {
+    pub fn new(settings: &ParserSettings) -> Self {
+        let patterns = if settings.mode == ParseMode::Regex {
+            settings
+                .patterns
+                .iter()
+                .filter(|pattern| !pattern.is_empty())
+                .filter_map(|pattern| match Regex::new(pattern) {
+                    Ok(regex) => Some(regex),
+                    Err(err) => {
+                        log::error!("invalid parser regex {:?}: {}", pattern, err);
+                        None
+                    }
+                })
+                .collect()
+        } else {
+            vec![]
+        };
+        Parser {
+            mode: settings.mode.clone(),
+            patterns,
+            number_format: settings.number_format,
+        }
+    }
+
+    /// Returns the named values of a line, or `None` if the values are taken by position
+    /// (numbers mode). Lines without values give an empty list.
+    pub fn named_values(&self, payload: &str) -> Option<Vec<(String, f64)>> {
+        match self.mode {
+            ParseMode::Numbers => None,
+            ParseMode::KeyValue => Some(parse_key_values(payload)),
+            ParseMode::Regex => Some(parse_captures(&self.patterns, payload)),
+            ParseMode::Json => Some(parse_json(payload)),
+            ParseMode::Tag => Some(parse_tagged(payload, &self.number_format)),
+            // sentences with a wrong checksum are dropped
+            ParseMode::Nmea => Some(parse_nmea(payload).unwrap_or_default()),
+        }
+    }
+}
+
+/// Returns the values of a tagged line like `BAT,3.71,120` as the channels `BAT.0` and
+/// `BAT.1`, fields that are no numbers are skipped. Lines without tag give an empty list.
+pub fn parse_tagged(payload: &str, format: &NumberFormat) -> Vec<(String, f64)> {
+    let mut fields = payload.split(format.separators()).map(str::trim);
+    let tag = fields.next().unwrap_or_default();
+    if tag.is_empty() || format.parse(tag).is_some() {
+        return vec![];
+    }
+    fields
+        .enumerate()
+        .filter_map(|(i, field)| {
+            let value = format.parse(field)?;
+            Some((format!("{tag}.{i}"), value))
+        })
+        .collect()
+}
+
+/// Returns the channel group of a tag mode channel, e.g. `IMU` for `IMU.0`.
+pub fn channel_group(name: &str) -> &str {
+    name.rsplit_once('.').map_or(name, |(group, _)| group)
+}
+
+/// Splits a line into its values like the numbers mode, but strictly: empty fields are
+/// missing values (NaN), a field that is no number makes the line malformed (`None`).
+/// A single trailing separator (`1,2,3,`) does not add a field.
+pub fn parse_fields(payload: &str, format: &NumberFormat) -> Option<Vec<f64>> {
+    let separators = format.separators();
+    let payload = payload.trim_end_matches(|c: char| c.is_whitespace() && !separators.contains(&c));
+    let payload = payload.strip_suffix(separators).unwrap_or(payload);
+    payload
+        .split(separators)
+        .map(|field| {
+            let field = field.trim();
+            if field.is_empty() {
+                Some(f64::NAN)
+            } else {
+                format.parse(field)
+            }
+        })
+        .collect()
+}
+
+/// Returns the numeric fields of a JSON object or array, nested fields and array elements
+/// are joined with dots (`imu.ax`, `acc.0`). Other lines give an empty list.
+pub fn parse_json(payload: &str) -> Vec<(String, f64)> {
+    let mut values = vec![];
+    if let Ok(value @ (Value::Object(_) | Value::Array(_))) =
+        serde_json::from_str::<Value>(payload.trim())
+    {
+        flatten_json("", &value, &mut values);
+    }
+    values
+}
+
+fn flatten_json(name: &str, value: &Value, values: &mut Vec<(String, f64)>) {
+    let join = |key: &str| {
+        if name.is_empty() {
+            key.to_string()
+        } else {
+            format!("{name}.{key}")
+        }
+    };
+    match value {
+        Value::Number(number) => {
+            if let Some(number) = number.as_f64() {
+                values.push((name.to_string(), number));
+            }
+        }
+        Value::Object(fields) => {
+            for (key, field) in fields {
+                flatten_json(&join(key), field, values);
+            }
+        }
+        Value::Array(items) => {
+            for (i, item) in items.iter().enumerate() {
+                flatten_json(&join(&i.to_string()), item, values);
+            }
+        }
+        Value::Null | Value::Bool(_) | Value::String(_) => {}
+    }
+}
+
+/// Indents a JSON object or array for the console, `None` if the line is no JSON.
+pub fn pretty_json(payload: &str) -> Option<String> {
+    match serde_json::from_str::<Value>(payload.trim()) {
+        Ok(value @ (Value::Object(_) | Value::Array(_))) => {
+            serde_json::to_string_pretty(&value).ok()
+        }
+        _ => None,
+    }
+}
+
+/// Takes the time of the samples from the device time column, see [`ParserSettings::x_field`].
+pub struct TimeBase {
+    column: String,
+    /// In tag mode an index selects the column of each group.
+    tagged: bool,
+    unit: TimeUnit,
+    wrap: bool,
+    last: Option<f64>,
+    /// Added to the counter for the wrap-arounds so far.
+    offset: f64,
+    /// Counter and offset of the last time taken, until its sample is accepted.
+    pending: Option<(f64, f64)>,
+}
+
+impl TimeBase {
+    pub fn new(settings: &ParserSettings) -> Self {
+        TimeBase {
+            column: settings.x_field.trim().to_string(),
+            tagged: settings.mode == ParseMode::Tag,
+            unit: settings.x_unit,
+            wrap: settings.x_wrap,
+            last: None,
+            offset: 0.0,
+            pending: None,
+        }
+    }
+
+    /// Removes the time column from the named values and returns the time in ms.
+    /// Without time column this is `arrival`, `None` if the values miss the time column.
+    /// The wrap-around tracking only moves on with [`TimeBase::commit`].
+    pub fn take_named(&mut self, values: &mut Vec<(String, f64)>, arrival: f64) -> Option<f64> {
+        if self.column.is_empty() {
+            return Some(arrival);
+        }
+        let i = values.iter().position(|(name, _)| {
+            *name == self.column
+                || (self.tagged
+                    && name.rsplit_once('.').map(|(_, index)| index) == Some(self.column.as_str()))
+        })?;
+        let (_, time) = values.remove(i);
+        Some(self.time_ms(time))
+    }
+
+    /// Like [`TimeBase::take_named`], for values by position with the index as time column.
+    pub fn take_indexed(&mut self, values: &mut Vec<f64>, arrival: f64) -> Option<f64> {
+        if self.column.is_empty() {
+            return Some(arrival);
+        }
+        let Ok(i) = self.column.parse::<usize>() else {
+            log::error!("time column {:?} is no column index", self.column);
+            self.column.clear();
+            return Some(arrival);
+        };
+        if i >= values.len() || values[i].is_nan() {
+            return None;
+        }
+        let time = values.remove(i);
+        Some(self.time_ms(time))
+    }
+
+    /// Accepts the sample of the last time taken, so a dropped line (e.g. with a garbage
+    /// counter) can not cause a wrap-around.
+    pub fn commit(&mut self) {
+        if let Some((last, offset)) = self.pending.take() {
+            self.last = Some(last);
+            self.offset = offset;
+        }
+    }
+
+    fn time_ms(&mut self, time: f64) -> f64 {
+        let mut time = time;
+        if self.wrap {
+            const RANGE: f64 = 4_294_967_296.0;
+            // a jump back by more than half the range is a wrap-around
+            let offset = if self.last.is_some_and(|last| last - time > RANGE / 2.0) {
+                self.offset + RANGE
+            } else {
+                self.offset
+            };
+            self.pending = Some((time, offset));
+            time += offset;
+        }
+        self.unit.to_ms(time)
+    }
+}
+
+/// Collects the named capture groups of all matching patterns that hold a number.
+pub fn parse_captures(patterns: &[Regex], payload: &str) -> Vec<(String, f64)> {
+    let mut values: Vec<(String, f64)> = vec![];
+    for pattern in patterns {
+        let Some(caps) = pattern.captures(payload) else {
+            continue;
+        };
+        for name in pattern.capture_names().flatten() {
+            let Some(value) = caps
+                .name(name)
+                .and_then(|m| m.as_str().trim().parse::<f64>().ok())
+            else {
+                continue;
+            };
+            if !values.iter().any(|(known, _)| known == name) {
+                values.push((name.to_string(), value));
+            }
+        }
+    }
+    values
+}
+
+/// Finds the `name=value` and `name:value` pairs of a line, e.g. `temp=23.4, hum: 41`.
+/// A name starts with a letter or `_`, text after a number (like a unit) is ignored.
+pub fn parse_key_values(payload: &str) -> Vec<(String, f64)> {
+    static PAIR: OnceLock<Regex> = OnceLock::new();
+    let pair = PAIR.get_or_init(|| {
+        Regex::new(
+            r"(?:^|[^\w.\-])([A-Za-z_][\w.\-]*)\s*[=:]\s*([-+]?(?:\d+\.?\d*|\.\d+)(?:[eE][-+]?\d+)?)",
+        )
+        .expect("invalid key/value regex")
+    });
+    let mut pairs: Vec<(String, f64)> = vec![];
+    for caps in pair.captures_iter(payload) {
+        let Ok(value) = caps[2].parse::<f64>() else {
+            continue;
+        };
+        // the first value of a name counts
+        if !pairs.iter().any(|(name, _)| *name == caps[1]) {
+            pairs.push((caps[1].to_string(), value));
+        }
+    }
+    pairs
+}
@@ -0,0 +1,373 @@
+use crate::framing::parse_escaped;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Type of a field in a binary frame.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub enum FieldType {
+    U8,
+    I8,
+    U16,
+    I16,
+    U32,
+    I32,
+    #[default]
+    F32,
+    F64,
+}
+
+impl FieldType {
+    pub const ALL: [FieldType; 8] = [
+        FieldType::U8,
+        FieldType::I8,
+        FieldType::U16,
+        FieldType::I16,
+        FieldType::U32,
+        FieldType::I32,
+        FieldType::F32,
+        FieldType::F64,
+    ];
+
+    pub fn size(&self) -> usize {
+        match self {
+            FieldType::U8 | FieldType::I8 => 1,
+            FieldType::U16 | FieldType::I16 => 2,
+            FieldType::U32 | FieldType::I32 | FieldType::F32 => 4,
+            FieldType::F64 => 8,
+        }
+    }
+
+    /// Reads the value from exactly [`FieldType::size`] bytes.
+    fn read(&self, bytes: &[u8], endianness: Endianness) -> f64 {
+        macro_rules! read {
+            ($t:ty) => {{
+                let bytes = bytes.try_into().expect("field size mismatch");
+                match endianness {
+                    Endianness::Little => <$t>::from_le_bytes(bytes) as f64,
+                    Endianness::Big => <$t>::from_be_bytes(bytes) as f64,
+                }
+            }};
+        }
+        match self {
+            FieldType::U8 => bytes[0] as f64,
+            FieldType::I8 => bytes[0] as i8 as f64,
+            FieldType::U16 => read!(u16),
+            FieldType::I16 => read!(i16),
+            FieldType::U32 => read!(u32),
+            FieldType::I32 => read!(i32),
+            FieldType::F32 => read!(f32),
+            FieldType::F64 => read!(f64),
+        }
+    }
+}
+
+impl fmt::Display for FieldType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            FieldType::U8 => write!(f, "u8"),
+            FieldType::I8 => write!(f, "i8"),
+            FieldType::U16 => write!(f, "u16"),
+            FieldType::I16 => write!(f, "i16"),
+            FieldType::U32 => write!(f, "u32"),
+            FieldType::I32 => write!(f, "i32"),
+            FieldType::F32 => write!(f, "f32"),
+            FieldType::F64 => write!(f, "f64"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub enum Endianness {
+    #[default]
+    Little,
+    Big,
+}
+
+impl Endianness {
+    pub const ALL: [Endianness; 2] = [Endianness::Little, Endianness::Big];
+}
+
+impl fmt::Display for Endianness {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Endianness::Little => write!(f, "Little Endian"),
+            Endianness::Big => write!(f, "Big Endian"),
+        }
+    }
+}
+
+/// Optional length field after the sync bytes, it holds the number of bytes of the fields.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub enum LengthField {
+    #[default]
+    None,
+    U8,
+    U16,
+}
+
+impl LengthField {
+    pub const ALL: [LengthField; 3] = [LengthField::None, LengthField::U8, LengthField::U16];
+
+    pub fn size(&self) -> usize {
+        match self {
+            LengthField::None => 0,
+            LengthField::U8 => 1,
+            LengthField::U16 => 2,
+        }
+    }
+}
+
+impl fmt::Display for LengthField {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            LengthField::None => write!(f, "No Length"),
+            LengthField::U8 => write!(f, "u8 Length"),
+            LengthField::U16 => write!(f, "u16 Length"),
+        }
+    }
+}
+
+/// Checksum at the end of a frame, calculated over the length field and the fields.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub enum Checksum {
+    #[default]
+    None,
+    /// Sum of the bytes modulo 256.
+    Sum8,
+    /// CRC-16/CCITT-FALSE (polynomial 0x1021, initial value 0xFFFF).
+    Crc16Ccitt,
+    /// CRC-32 as used by Ethernet and zlib.
+    Crc32,
+}
+
+impl Checksum {
+    pub const ALL: [Checksum; 4] = [
+        Checksum::None,
+        Checksum::Sum8,
+        Checksum::Crc16Ccitt,
+        Checksum::Crc32,
+    ];
+
+    pub fn size(&self) -> usize {
+        match self {
+            Checksum::None => 0,
+            Checksum::Sum8 => 1,
+            Checksum::Crc16Ccitt => 2,
+            Checksum::Crc32 => 4,
+        }
+    }
+
+    fn calculate(&self, data: &[u8]) -> u32 {
+        match self {
+            Checksum::None => 0,
+            Checksum::Sum8 => data.iter().fold(0u8, |sum, b| sum.wrapping_add(*b)) as u32,
+            Checksum::Crc16Ccitt => crc16_ccitt(data) as u32,
+            Checksum::Crc32 => crc32(data),
+        }
+    }
+}
+
+impl fmt::Display for Checksum {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Checksum::None => write!(f, "No Checksum"),
+            Checksum::Sum8 => write!(f, "Sum8"),
+            Checksum::Crc16Ccitt => write!(f, "CRC-16/CCITT"),
+            Checksum::Crc32 => write!(f, "CRC-32"),
+        }
+    }
+}
+
+/// A field of a binary frame, its value is `raw * scale + offset`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SchemaField {
+    pub name: String,
+    pub field_type: FieldType,
+    pub scale: f64,
+    pub offset: f64,
+}
+
+impl SchemaField {
+    pub fn new(name: String) -> Self {
+        SchemaField {
+            name,
+            field_type: FieldType::default(),
+            scale: 1.0,
+            offset: 0.0,
+        }
+    }
+}
+
+/// Layout of a binary frame: sync bytes, optional length, the fields and an optional checksum.
+/// Length and checksum use the endianness of the fields.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FrameSchema {
+    /// Written with escapes like the EOL setting, e.g. `\xAA\x55`.
+    pub sync: String,
+    pub length: LengthField,
+    pub endianness: Endianness,
+    pub fields: Vec<SchemaField>,
+    pub checksum: Checksum,
+}
+
+impl FrameSchema {
+    pub const fn new() -> Self {
+        FrameSchema {
+            sync: String::new(),
+            length: LengthField::None,
+            endianness: Endianness::Little,
+            fields: Vec::new(),
+            checksum: Checksum::None,
+        }
+    }
+
+    pub fn sync_bytes(&self) -> Vec<u8> {
+        parse_escaped(&self.sync)
+    }
+
+    /// Number of bytes of all fields.
+    fn fields_len(&self) -> usize {
+        self.fields
+            .iter()
+            .map(|field| field.field_type.size())
+            .sum()
+    }
+
+    /// Number of bytes of a whole frame including sync and checksum.
+    pub fn frame_len(&self) -> usize {
+        self.sync_bytes().len() + self.length.size() + self.fields_len() + self.checksum.size()
+    }
+
+    /// Column names of the decoded values.
+    pub fn names(&self) -> Vec<String> {
+        self.fields
+            .iter()
+            .enumerate()
+            .map(|(i, field)| {
+                if field.name.is_empty() {
+                    format!("Field {i}")
+                } else {
+                    field.name.clone()
+                }
+            })
+            .collect()
+    }
+
+    /// Checks a frame of [`FrameSchema::frame_len`] bytes (starting with the sync bytes)
+    /// and returns the scaled value of each field.
+    pub fn decode(&self, frame: &[u8]) -> Result<Vec<f64>, FrameError> {
+        if frame.len() != self.frame_len() {
+            return Err(FrameError::Size(frame.len()));
+        }
+        let sync_len = self.sync_bytes().len();
+        if frame[..sync_len] != self.sync_bytes() {
+            return Err(FrameError::Sync);
+        }
+        let checksum_start = frame.len() - self.checksum.size();
+        if self.checksum != Checksum::None {
+            let expected = self.checksum.calculate(&frame[sync_len..checksum_start]);
+            let received = self.read_uint(&frame[checksum_start..]);
+            if received != expected {
+                return Err(FrameError::Checksum { expected, received });
+            }
+        }
+        let mut pos = sync_len;
+        if self.length != LengthField::None {
+            let length = self.read_uint(&frame[pos..pos + self.length.size()]) as usize;
+            if length != self.fields_len() {
+                return Err(FrameError::Length(length));
+            }
+            pos += self.length.size();
+        }
+        let mut values = Vec::with_capacity(self.fields.len());
+        for field in &self.fields {
+            let size = field.field_type.size();
+            let raw = field
+                .field_type
+                .read(&frame[pos..pos + size], self.endianness);
+            values.push(raw * field.scale + field.offset);
+            pos += size;
+        }
+        Ok(values)
+    }
+
+    /// Text of the decoded values for the console, e.g. `ax=0.1, ay=-2`.
+    pub fn describe(&self, values: &[f64]) -> String {
+        self.names()
+            .iter()
+            .zip(values)
+            .map(|(name, value)| format!("{name}={value}"))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    fn read_uint(&self, bytes: &[u8]) -> u32 {
+        let mut value = 0;
+        for i in 0..bytes.len() {
+            let byte = match self.endianness {
+                Endianness::Little => bytes[bytes.len() - 1 - i],
+                Endianness::Big => bytes[i],
+            };
+            value = (value << 8) | byte as u32;
+        }
+        value
+    }
+}
+
+impl Default for FrameSchema {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FrameError {
+    Size(usize),
+    Sync,
+    Length(usize),
+    Checksum { expected: u32, received: u32 },
+}
+
+impl fmt::Display for FrameError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FrameError::Size(size) => write!(f, "wrong frame size of {size} bytes"),
+            FrameError::Sync => write!(f, "missing sync bytes"),
+            FrameError::Length(length) => write!(f, "wrong length of {length} bytes"),
+            FrameError::Checksum { expected, received } => write!(
+                f,
+                "checksum mismatch, expected 0x{expected:x}, received 0x{received:x}"
+            ),
+        }
+    }
+}
+
+fn crc16_ccitt(data: &[u8]) -> u16 {
+    let mut crc: u16 = 0xFFFF;
+    for &byte in data {
+        crc ^= (byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc: u32 = 0xFFFF_FFFF;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
@@ -1,16 +1,48 @@
-use crossbeam_channel::{Receiver, Sender};
+use crossbeam_channel::{select, Receiver, Sender};
 use eframe::egui::Color32;
 use preferences::Preferences;
 use serde::{Deserialize, Serialize};
-use serialport::{DataBits, FlowControl, Parity, SerialPort, StopBits};
-use std::io::{BufRead, BufReader};
+use serialport::{
+    ClearBuffer, DataBits, FlowControl, Parity, SerialPort, SerialPortType, StopBits,
+};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, RwLock};
 use std::time::{Duration, Instant};
 
 use crate::color_picker::COLORS;
-use crate::data::{get_epoch_ms, SerialDirection};
+use crate::data::{timestamps, SerialDirection};
+use crate::derived::DerivedChannel;
+use crate::encoding::TextEncoding;
+use crate::framing::{plausibility, Framer, Framing};
+use crate::network::{is_network_device, open_network_port};
+use crate::parser::ParserSettings;
+use crate::simulation::{is_simulated_device, open_simulated_port, Simulation, SIMULATED_DEVICES};
+use crate::transfer::{FileTransfer, Transfer, TransferProgress, TransferState};
 use crate::{Packet, APP_INFO, PREFERENCES_KEY_SERIAL};
 
+/// Read timeout used when the device is configured with a timeout of zero.
+const DEFAULT_TIMEOUT: Duration = Duration::from_millis(100);
+/// Baud rates tried by the auto-baud detection, the most common ones first.
+const AUTO_BAUD_RATES: [u32; 14] = [
+    115200, 9600, 57600, 38400, 19200, 230400, 460800, 921600, 250000, 500000, 1000000, 74880,
+    1843200, 4800,
+];
+/// Time spent listening at each candidate baud rate.
+const AUTO_BAUD_WINDOW: Duration = Duration::from_millis(300);
+/// Candidates with less received data are not rated.
+const AUTO_BAUD_MIN_BYTES: usize = 8;
+/// A candidate rated at least this good is accepted right away.
+const AUTO_BAUD_GOOD_ENOUGH: f64 = 0.99;
+/// Read timeout while a file transfer is running, so that it is not slowed down by the reads.
+const TRANSFER_TIMEOUT: Duration = Duration::from_millis(5);
+/// Interval at which the modem input lines are read.
+const MODEM_POLL_INTERVAL: Duration = Duration::from_millis(50);
+/// How often the writer checks whether a file transfer has finished.
+const TRANSFER_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SerialDevices {
     pub devices: Vec<Device>,
@@ -20,6 +52,12 @@ pub struct SerialDevices {
     pub color_vals: Vec<Vec<f32>>,
     pub number_of_plots: Vec<usize>,
     pub number_of_highlights: Vec<usize>,
+    /// Plot of each channel group (tag mode), groups without entry are shown in all plots.
+    #[serde(default)]
+    pub group_plots: Vec<BTreeMap<String, usize>>,
+    /// Channels computed from the columns of each device.
+    #[serde(default)]
+    pub derived_channels: Vec<Vec<DerivedChannel>>,
 }
 
 impl Default for SerialDevices {
@@ -32,6 +70,8 @@ impl Default for SerialDevices {
             color_vals: vec![vec![0.0]],
             number_of_plots: vec![1],
             number_of_highlights: vec![1],
+            group_plots: vec![BTreeMap::new()],
+            derived_channels: vec![vec![]],
         }
     }
 }
@@ -64,32 +104,193 @@ pub fn clear_serial_settings() {
     }
 }
 
+/// USB identity of a port, used to recognize a device when it comes back under another name.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
+pub struct UsbIdentity {
+    pub vid: u16,
+    pub pid: u16,
+    pub serial_number: Option<String>,
+    pub manufacturer: Option<String>,
+    pub product: Option<String>,
+}
+
+impl UsbIdentity {
+    /// Returns true if both are the same physical device. Adapters without a serial number
+    /// can't be told apart, they never match.
+    pub fn same_device(&self, other: &UsbIdentity) -> bool {
+        self.serial_number.is_some()
+            && self.vid == other.vid
+            && self.pid == other.pid
+            && self.serial_number == other.serial_number
+    }
+
+    /// Short description for the device list, e.g. `Arduino Uno (2341:0043)`.
+    pub fn summary(&self) -> String {
+        match self.product.as_ref().or(self.manufacturer.as_ref()) {
+            Some(name) => format!("{} ({:04x}:{:04x})", name, self.vid, self.pid),
+            None => format!("{:04x}:{:04x}", self.vid, self.pid),
+        }
+    }
+}
+
+impl fmt::Display for UsbIdentity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "VID:PID: {:04x}:{:04x}", self.vid, self.pid)?;
+        writeln!(
+            f,
+            "Manufacturer: {}",
+            self.manufacturer.as_deref().unwrap_or("-")
+        )?;
+        writeln!(f, "Product: {}", self.product.as_deref().unwrap_or("-"))?;
+        write!(
+            f,
+            "Serial number: {}",
+            self.serial_number.as_deref().unwrap_or("-")
+        )
+    }
+}
+
+/// A port found on the system.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct PortInfo {
+    pub name: String,
+    pub usb: Option<UsbIdentity>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct Device {
     pub name: String,
+    /// Identity of the USB device behind the port, if known.
+    #[serde(default)]
+    pub usb: Option<UsbIdentity>,
     pub baud_rate: u32,
+    /// Detect the baud rate on connect, `baud_rate` then holds the detected rate.
+    #[serde(default)]
+    pub auto_baud: bool,
     pub data_bits: DataBits,
     pub flow_control: FlowControl,
     pub parity: Parity,
     pub stop_bits: StopBits,
+    /// Read timeout of the port, zero selects [`DEFAULT_TIMEOUT`].
     pub timeout: Duration,
+    #[serde(default)]
+    pub framing: Framing,
+    #[serde(default)]
+    pub simulation: Simulation,
+    /// Level of DTR, set on connect.
+    #[serde(default = "default_line_level")]
+    pub dtr: bool,
+    /// Level of RTS, set on connect (not used with hardware flow control).
+    #[serde(default = "default_line_level")]
+    pub rts: bool,
+    #[serde(default)]
+    pub reset_pulse: ResetPulse,
+    /// Length of a BREAK sent from the GUI.
+    #[serde(default = "default_break_duration")]
+    pub break_duration: Duration,
+    /// Encoding of the received and sent text.
+    #[serde(default)]
+    pub encoding: TextEncoding,
+    #[serde(default)]
+    pub parser: ParserSettings,
+}
+
+fn default_line_level() -> bool {
+    true
+}
+
+fn default_break_duration() -> Duration {
+    Duration::from_millis(250)
+}
+
+/// The modem output line(s) that are toggled for a reset.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Default)]
+pub enum ResetLine {
+    /// Arduino style auto-reset.
+    #[default]
+    Dtr,
+    /// ESP32 style auto-reset (RTS drives EN).
+    Rts,
+    Both,
+}
+
+impl ResetLine {
+    pub const ALL: [ResetLine; 3] = [ResetLine::Dtr, ResetLine::Rts, ResetLine::Both];
+}
+
+impl fmt::Display for ResetLine {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ResetLine::Dtr => write!(f, "DTR"),
+            ResetLine::Rts => write!(f, "RTS"),
+            ResetLine::Both => write!(f, "DTR+RTS"),
+        }
+    }
+}
+
+/// A reset pulse inverts the reset line(s) for `duration` and then restores their level.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ResetPulse {
+    pub line: ResetLine,
+    pub duration: Duration,
+}
+
+impl Default for ResetPulse {
+    fn default() -> Self {
+        ResetPulse {
+            line: ResetLine::Dtr,
+            duration: Duration::from_millis(100),
+        }
+    }
+}
+
+/// Commands for the serial thread besides the data to send.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SerialCommand {
+    /// Sends the reset pulse configured for the device.
+    ResetPulse,
+    /// Holds the line in the BREAK condition for the given duration.
+    Break(Duration),
+    /// Starts sending a file.
+    SendFile(FileTransfer),
+    /// Cancels the running file transfer.
+    CancelTransfer,
+}
+
+/// Levels of the modem input lines, `None` if the port does not report the line.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ModemLines {
+    pub cts: Option<bool>,
+    pub dsr: Option<bool>,
+    pub dcd: Option<bool>,
+    pub ri: Option<bool>,
 }
 
 impl Default for Device {
     fn default() -> Self {
         Device {
             name: "".to_string(),
+            usb: None,
             baud_rate: 9600,
+            auto_baud: false,
             data_bits: DataBits::Eight,
             flow_control: FlowControl::None,
             parity: Parity::None,
             stop_bits: StopBits::One,
             timeout: Duration::from_millis(0),
+            framing: Framing::Auto,
+            simulation: Simulation::default(),
+            dtr: true,
+            rts: true,
+            reset_pulse: ResetPulse::default(),
+            break_duration: default_break_duration(),
+            encoding: TextEncoding::default(),
+            parser: ParserSettings::default(),
         }
     }
 }
 
-pub fn serial_devices_thread(devices_lock: Arc<RwLock<Vec<String>>>) {
+pub fn serial_devices_thread(devices_lock: Arc<RwLock<Vec<PortInfo>>>) {
     loop {
         if let Ok(mut write_guard) = devices_lock.write() {
             *write_guard = available_devices();
@@ -98,27 +299,30 @@ pub fn serial_devices_thread(devices_lock: Arc<RwLock<Vec<String>>>) {
     }
 }
 
-fn serial_write(
-    port: &mut BufReader<Box<dyn SerialPort>>,
-    cmd: &[u8],
-) -> Result<usize, std::io::Error> {
-    let write_port = port.get_mut();
-    write_port.write(cmd)
+fn serial_write(port: &mut Box<dyn SerialPort>, cmd: &[u8]) -> Result<(), std::io::Error> {
+    port.write_all(cmd)?;
+    // waits until the data has been transmitted
+    port.flush()
 }
 
 fn serial_read(
     port: &mut BufReader<Box<dyn SerialPort>>,
-    serial_buf: &mut String,
+    serial_buf: &mut [u8],
 ) -> Result<usize, std::io::Error> {
-    port.read_line(serial_buf)
+    port.read(serial_buf)
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn serial_thread(
     send_rx: Receiver<String>,
+    serial_cmd_rx: Receiver<SerialCommand>,
     raw_data_tx: Sender<Packet>,
     device_lock: Arc<RwLock<Device>>,
-    devices_lock: Arc<RwLock<Vec<String>>>,
+    devices_lock: Arc<RwLock<Vec<PortInfo>>>,
     connected_lock: Arc<RwLock<bool>>,
+    modem_lock: Arc<RwLock<ModemLines>>,
+    transfer_lock: Arc<RwLock<Option<TransferProgress>>>,
+    t_zero: Instant,
 ) {
     let mut last_connected_device = Device::default();
     let mut connect_retry_backoff = Duration::from_millis(100);
@@ -135,25 +339,38 @@ pub fn serial_thread(
         if let Ok(mut connected) = connected_lock.write() {
             *connected = false;
         }
+        if let Ok(mut modem_lines) = modem_lock.write() {
+            *modem_lines = ModemLines::default();
+        }
 
-        let device = get_device(&devices_lock, &device_lock, &last_connected_device);
+        let mut device = match get_device(&devices_lock, &device_lock, &last_connected_device) {
+            Some(device) => device,
+            // the connection has been closed
+            None => return,
+        };
 
-        let mut port = match serialport::new(&device.name, device.baud_rate)
-            .timeout(Duration::from_millis(100))
-            .open()
-        {
-            Ok(p) => {
+        // reads and writes are done independently on two handles of the port
+        let open_result = open_port(&device).and_then(|p| {
+            let write_port = p.try_clone()?;
+            Ok((p, write_port))
+        });
+        let (mut port, write_port) = match open_result {
+            Ok((p, write_port)) => {
                 if let Ok(mut connected) = connected_lock.write() {
                     *connected = true;
                 }
 
                 log::info!(
-                    "Connected to serial port: {} @ baud = {}",
+                    "Connected to serial port: {} @ baud = {}, {}{}{}, flow control = {}",
                     device.name,
-                    device.baud_rate
+                    device.baud_rate,
+                    device.data_bits,
+                    parity_char(device.parity),
+                    device.stop_bits,
+                    device.flow_control
                 );
 
-                BufReader::new(p)
+                (BufReader::new(p), write_port)
             }
             Err(err) => {
                 if let Ok(mut write_guard) = device_lock.write() {
@@ -167,7 +384,33 @@ pub fn serial_thread(
         };
         connect_retry_backoff = Duration::from_millis(100);
 
-        let t_zero = Instant::now();
+        if device.auto_baud {
+            auto_baud(&mut port, &mut device, &device_lock);
+        }
+
+        // the writer stops when `stop_tx` is dropped
+        let (stop_tx, stop_rx) = crossbeam_channel::bounded::<()>(0);
+        let writer_send_rx = send_rx.clone();
+        let writer_raw_data_tx = raw_data_tx.clone();
+        let writer_device_lock = device_lock.clone();
+        // commands are held back while a file transfer owns the port
+        let transfer_active = Arc::new(AtomicBool::new(false));
+        let writer_transfer_active = transfer_active.clone();
+        std::thread::spawn(move || {
+            writer_thread(
+                write_port,
+                writer_send_rx,
+                stop_rx,
+                writer_raw_data_tx,
+                writer_device_lock,
+                writer_transfer_active,
+                t_zero,
+            );
+        });
+
+        let mut framer = Framer::new(device.framing.clone());
+        let mut last_modem_poll = Instant::now() - MODEM_POLL_INTERVAL;
+        let mut transfer: Option<Transfer> = None;
 
         #[cfg(not(target_os = "ios"))]
         let _awake = keepawake::Builder::default()
@@ -187,8 +430,54 @@ pub fn serial_thread(
                 break 'connected_loop;
             }
 
-            perform_writes(&mut port, &send_rx, &raw_data_tx, t_zero);
-            if perform_reads(&mut port, &raw_data_tx, t_zero) {
+            if let Some(new_device) = reconfigured(&device, &device_lock) {
+                if new_device.simulation != device.simulation && is_simulated_device(&device.name) {
+                    // restart the simulated device with the new settings
+                    log::info!("Restarting simulated device: {}", device.name);
+                    last_connected_device = new_device;
+                    break 'connected_loop;
+                }
+                match reconfigure_port(&mut port, &new_device) {
+                    Ok(()) => log::info!(
+                        "Reconfigured serial port: {} @ baud = {}, {}{}{}, flow control = {}",
+                        new_device.name,
+                        new_device.baud_rate,
+                        new_device.data_bits,
+                        parity_char(new_device.parity),
+                        new_device.stop_bits,
+                        new_device.flow_control
+                    ),
+                    Err(err) => log::error!("Error reconfiguring serial port: {}", err),
+                }
+                if new_device.framing != device.framing {
+                    framer = Framer::new(new_device.framing.clone());
+                }
+                let start_auto_baud = new_device.auto_baud && !device.auto_baud;
+                device = new_device;
+                if start_auto_baud {
+                    auto_baud(&mut port, &mut device, &device_lock);
+                }
+            }
+
+            perform_commands(
+                &mut port,
+                &serial_cmd_rx,
+                &raw_data_tx,
+                &device,
+                &mut transfer,
+                t_zero,
+            );
+            transfer_active.store(transfer.is_some(), Ordering::Relaxed);
+            if last_modem_poll.elapsed() >= MODEM_POLL_INTERVAL {
+                let modem_lines = read_modem_lines(&mut port);
+                if let Ok(mut write_guard) = modem_lock.write() {
+                    *write_guard = modem_lines;
+                }
+                last_modem_poll = Instant::now();
+            }
+            let Some(received) =
+                perform_reads(&mut port, &mut framer, &raw_data_tx, &device, t_zero)
+            else {
                 // A non-timeout read error typically means the device/driver went away.
                 // Break out and let reconnect logic take over instead of spinning on errors.
                 if let Ok(mut write_guard) = device_lock.write() {
@@ -197,26 +486,55 @@ pub fn serial_thread(
                 last_connected_device = device.clone();
                 std::thread::sleep(Duration::from_millis(50));
                 break 'connected_loop;
+            };
+            perform_transfer(&mut port, &mut transfer, &received, &transfer_lock, &device);
+        }
+        if let Some(mut transfer) = transfer {
+            transfer.fail("the connection was closed");
+            if let Ok(mut write_guard) = transfer_lock.write() {
+                *write_guard = Some(transfer.progress());
             }
         }
+        std::mem::drop(stop_tx);
         std::mem::drop(port);
     }
 }
 
-fn available_devices() -> Vec<String> {
+fn available_devices() -> Vec<PortInfo> {
     serialport::available_ports()
         .unwrap()
-        .iter()
-        .map(|p| p.port_name.clone())
+        .into_iter()
+        .map(|p| PortInfo {
+            name: p.port_name,
+            usb: match p.port_type {
+                SerialPortType::UsbPort(info) => Some(UsbIdentity {
+                    vid: info.vid,
+                    pid: info.pid,
+                    serial_number: info.serial_number,
+                    manufacturer: info.manufacturer,
+                    product: info.product,
+                }),
+                _ => None,
+            },
+        })
+        .chain(SIMULATED_DEVICES.iter().map(|d| PortInfo {
+            name: d.to_string(),
+            usb: None,
+        }))
         .collect()
 }
 
 fn get_device(
-    devices_lock: &Arc<RwLock<Vec<String>>>,
+    devices_lock: &Arc<RwLock<Vec<PortInfo>>>,
     device_lock: &Arc<RwLock<Device>>,
     last_connected_device: &Device,
-) -> Device {
+) -> Option<Device> {
     loop {
+        // nobody else holds the device lock anymore, the connection has been closed
+        if Arc::strong_count(device_lock) == 1 {
+            return None;
+        }
+
         let devices = if let Ok(read_guard) = devices_lock.read() {
             read_guard.clone()
         } else {
@@ -224,27 +542,249 @@ fn get_device(
         };
 
         // do reconnect
-        if devices.contains(&last_connected_device.name) {
-            if let Ok(mut device) = device_lock.write() {
-                device.name = last_connected_device.name.clone();
-                device.baud_rate = last_connected_device.baud_rate;
+        if let Some(device) = locate(&devices, last_connected_device) {
+            if let Ok(mut write_guard) = device_lock.write() {
+                *write_guard = device.clone();
             }
-            return last_connected_device.clone();
+            return Some(device);
         }
 
-        if let Ok(device) = device_lock.read() {
-            if devices.contains(&device.name) {
-                return device.clone();
+        let located = match device_lock.read() {
+            Ok(device) => locate(&devices, &device),
+            Err(_) => None,
+        };
+        if let Some(device) = located {
+            if let Ok(mut write_guard) = device_lock.write() {
+                *write_guard = device.clone();
             }
+            return Some(device);
         }
         std::thread::sleep(Duration::from_millis(150));
     }
 }
 
+fn open_port(device: &Device) -> Result<Box<dyn SerialPort>, serialport::Error> {
+    let mut port = if is_network_device(&device.name) {
+        open_network_port(device, port_timeout(device))
+    } else if is_simulated_device(&device.name) {
+        open_simulated_port(device, port_timeout(device))
+    } else {
+        serialport::new(&device.name, device.baud_rate)
+            .data_bits(device.data_bits)
+            .flow_control(device.flow_control)
+            .parity(device.parity)
+            .stop_bits(device.stop_bits)
+            .timeout(port_timeout(device))
+            .open()
+    }?;
+    // not all drivers support the modem lines, that's no reason to fail
+    if let Err(err) = write_modem_lines(&mut port, device) {
+        log::warn!("Error setting DTR/RTS: {}", err);
+    }
+    Ok(port)
+}
+
+fn write_modem_lines(
+    port: &mut Box<dyn SerialPort>,
+    device: &Device,
+) -> Result<(), serialport::Error> {
+    port.write_data_terminal_ready(device.dtr)?;
+    // RTS is driven by the port itself with hardware flow control
+    if device.flow_control != FlowControl::Hardware {
+        port.write_request_to_send(device.rts)?;
+    }
+    Ok(())
+}
+
+fn read_modem_lines(port: &mut BufReader<Box<dyn SerialPort>>) -> ModemLines {
+    let port = port.get_mut();
+    ModemLines {
+        cts: port.read_clear_to_send().ok(),
+        dsr: port.read_data_set_ready().ok(),
+        dcd: port.read_carrier_detect().ok(),
+        ri: port.read_ring_indicator().ok(),
+    }
+}
+
+/// Returns the current name of the port of the device, looked up by the USB identity if
+/// the device has a unique one and by the port name otherwise.
+/// Network devices are not enumerated, they are considered available at all times.
+fn find_port(devices: &[PortInfo], device: &Device) -> Option<String> {
+    if is_network_device(&device.name) {
+        return Some(device.name.clone());
+    }
+    match device
+        .usb
+        .as_ref()
+        .filter(|usb| usb.serial_number.is_some())
+    {
+        Some(usb) => devices
+            .iter()
+            .find(|port| port.usb.as_ref().is_some_and(|u| u.same_device(usb)))
+            .map(|port| port.name.clone()),
+        None => devices
+            .iter()
+            .find(|port| port.name == device.name)
+            .map(|port| port.name.clone()),
+    }
+}
+
+/// Returns the device with the name of its port if it is available.
+fn locate(devices: &[PortInfo], device: &Device) -> Option<Device> {
+    let name = find_port(devices, device)?;
+    let mut device = device.clone();
+    if device.name != name {
+        log::info!("{} is now available as {}", device.name, name);
+        device.name = name;
+    }
+    Some(device)
+}
+
+fn port_timeout(device: &Device) -> Duration {
+    // a zero timeout would make the read loop spin
+    if device.timeout.is_zero() {
+        DEFAULT_TIMEOUT
+    } else {
+        device.timeout
+    }
+}
+
+/// Time it takes to transmit one byte, used to interpolate the arrival of the bytes of a read.
+fn byte_time(device: &Device) -> Duration {
+    if is_network_device(&device.name) || is_simulated_device(&device.name) || device.baud_rate == 0
+    {
+        // the bytes do not arrive at the line speed
+        return Duration::ZERO;
+    }
+    let data_bits = match device.data_bits {
+        DataBits::Five => 5,
+        DataBits::Six => 6,
+        DataBits::Seven => 7,
+        DataBits::Eight => 8,
+    };
+    let parity_bits = if device.parity == Parity::None { 0 } else { 1 };
+    let stop_bits = match device.stop_bits {
+        StopBits::One => 1,
+        StopBits::Two => 2,
+    };
+    // plus the start bit
+    let bits = 1 + data_bits + parity_bits + stop_bits;
+    Duration::from_secs(bits) / device.baud_rate
+}
+
+fn parity_char(parity: Parity) -> char {
+    match parity {
+        Parity::None => 'N',
+        Parity::Odd => 'O',
+        Parity::Even => 'E',
+    }
+}
+
+/// Detects the baud rate of the device and stores it in the device settings.
+fn auto_baud(
+    port: &mut BufReader<Box<dyn SerialPort>>,
+    device: &mut Device,
+    device_lock: &Arc<RwLock<Device>>,
+) {
+    log::info!("Detecting baud rate of {}", device.name);
+    match detect_baud_rate(port, &device.framing) {
+        Some(baud_rate) => {
+            log::info!("Detected baud rate of {}: {}", device.name, baud_rate);
+            device.baud_rate = baud_rate;
+            if let Ok(mut write_guard) = device_lock.write() {
+                if write_guard.name == device.name {
+                    write_guard.baud_rate = baud_rate;
+                }
+            }
+        }
+        None => {
+            log::warn!(
+                "Unable to detect the baud rate of {}, no data received. Using {}",
+                device.name,
+                device.baud_rate
+            );
+            if let Err(err) = port.get_mut().set_baud_rate(device.baud_rate) {
+                log::error!("Error setting baud rate: {}", err);
+            }
+        }
+    }
+}
+
+/// Listens at each of the [`AUTO_BAUD_RATES`] and returns the one whose data looks most
+/// plausible for the framing. The port is left at the returned rate.
+fn detect_baud_rate(port: &mut BufReader<Box<dyn SerialPort>>, framing: &Framing) -> Option<u32> {
+    let mut best: Option<(u32, f64)> = None;
+    for baud_rate in AUTO_BAUD_RATES {
+        if let Err(err) = port.get_mut().set_baud_rate(baud_rate) {
+            log::debug!("baud rate {} not supported: {}", baud_rate, err);
+            continue;
+        }
+        // drop what has been received at the previous rate
+        let _ = port.get_mut().clear(ClearBuffer::Input);
+        let buffered = port.buffer().len();
+        port.consume(buffered);
+
+        let mut received = vec![];
+        let start = Instant::now();
+        while start.elapsed() < AUTO_BAUD_WINDOW {
+            let mut buf = [0; 1024];
+            match serial_read(port, &mut buf) {
+                Ok(n) => received.extend_from_slice(&buf[..n]),
+                Err(ref e) if e.kind() == std::io::ErrorKind::TimedOut => {}
+                Err(_) => break,
+            }
+        }
+        if received.len() < AUTO_BAUD_MIN_BYTES {
+            continue;
+        }
+        let score = plausibility(framing, &received);
+        log::debug!(
+            "baud rate {}: {} bytes, score {:.2}",
+            baud_rate,
+            received.len(),
+            score
+        );
+        if best.is_none_or(|(_, best_score)| score > best_score) {
+            best = Some((baud_rate, score));
+        }
+        if score >= AUTO_BAUD_GOOD_ENOUGH {
+            break;
+        }
+    }
+    let (baud_rate, _) = best?;
+    port.get_mut().set_baud_rate(baud_rate).ok()?;
+    Some(baud_rate)
+}
+
+/// Returns the new settings if the line settings of the connected device were changed in the GUI.
+fn reconfigured(device: &Device, device_lock: &Arc<RwLock<Device>>) -> Option<Device> {
+    if let Ok(read_guard) = device_lock.try_read() {
+        if read_guard.name == device.name && *read_guard != *device {
+            return Some(read_guard.clone());
+        }
+    }
+    None
+}
+
+fn reconfigure_port(
+    port: &mut BufReader<Box<dyn SerialPort>>,
+    device: &Device,
+) -> Result<(), serialport::Error> {
+    let port = port.get_mut();
+    port.set_baud_rate(device.baud_rate)?;
+    port.set_data_bits(device.data_bits)?;
+    port.set_flow_control(device.flow_control)?;
+    port.set_parity(device.parity)?;
+    port.set_stop_bits(device.stop_bits)?;
+    port.set_timeout(port_timeout(device))?;
+    write_modem_lines(port, device)?;
+    Ok(())
+}
+
 fn disconnected(
     device: &Device,
     device_lock: &Arc<RwLock<Device>>,
-    devices_lock: &Arc<RwLock<Vec<String>>>,
+    devices_lock: &Arc<RwLock<Vec<PortInfo>>>,
     last_connected_device: &mut Device,
 ) -> bool {
     // disconnection by button press
@@ -258,7 +798,7 @@ fn disconnected(
 
     if let Ok(devices) = devices_lock.try_read() {
         // other types of disconnection (e.g. unplugging, power down)
-        if !devices.contains(&device.name) {
+        if find_port(&devices, device).as_ref() != Some(&device.name) {
             if let Ok(mut write_guard) = device_lock.try_write() {
                 write_guard.name.clear();
             }
@@ -270,63 +810,237 @@ fn disconnected(
     false
 }
 
-fn perform_writes(
+fn perform_commands(
     port: &mut BufReader<Box<dyn SerialPort>>,
-    send_rx: &Receiver<String>,
+    serial_cmd_rx: &Receiver<SerialCommand>,
     raw_data_tx: &Sender<Packet>,
+    device: &Device,
+    transfer: &mut Option<Transfer>,
     t_zero: Instant,
 ) {
-    if let Ok(cmd) = send_rx.try_recv() {
-        if let Err(e) = serial_write(port, cmd.as_bytes()) {
-            log::error!("Error sending command: {e}");
-            return;
+    if let Ok(cmd) = serial_cmd_rx.try_recv() {
+        match cmd {
+            SerialCommand::ResetPulse => match reset_pulse(port.get_mut(), device) {
+                Ok(()) => log::info!(
+                    "Sent reset pulse on {} ({} ms)",
+                    device.reset_pulse.line,
+                    device.reset_pulse.duration.as_millis()
+                ),
+                Err(err) => log::error!("Error sending reset pulse: {}", err),
+            },
+            SerialCommand::Break(duration) => {
+                let (relative_time, absolute_time) = timestamps(Instant::now(), t_zero);
+                let packet = Packet {
+                    relative_time,
+                    absolute_time,
+                    direction: SerialDirection::Break,
+                    payload: format!("<BREAK {} ms>", duration.as_millis()),
+                    raw: vec![],
+                    encoding: device.encoding,
+                };
+                if let Err(err) = send_break(port.get_mut(), duration) {
+                    log::error!("Error sending break: {}", err);
+                    return;
+                }
+                raw_data_tx
+                    .send(packet)
+                    .expect("failed to send raw data (break)");
+            }
+            SerialCommand::SendFile(request) => {
+                if transfer.is_some() {
+                    log::error!("A file transfer is already running");
+                    return;
+                }
+                match Transfer::new(&request, Instant::now()) {
+                    Ok(new_transfer) => {
+                        log::info!("Sending {:?} ({})", request.path, request.protocol);
+                        if let Err(err) = port.get_mut().set_timeout(TRANSFER_TIMEOUT) {
+                            log::error!("Error setting timeout: {}", err);
+                        }
+                        *transfer = Some(new_transfer);
+                    }
+                    Err(err) => log::error!("failed to read {:?}: {}", request.path, err),
+                }
+            }
+            SerialCommand::CancelTransfer => {
+                if let Some(transfer) = transfer {
+                    let cancel = transfer.cancel();
+                    if let Err(err) = serial_write(port.get_mut(), &cancel) {
+                        log::error!("Error cancelling the transfer: {}", err);
+                    }
+                }
+            }
         }
+    }
+}
 
-        let packet = Packet {
-            relative_time: Instant::now().duration_since(t_zero).as_millis() as f64,
-            absolute_time: get_epoch_ms() as f64,
-            direction: SerialDirection::Send,
-            payload: cmd,
-        };
-        raw_data_tx
-            .send(packet)
-            .expect("failed to send raw data (cmd)");
+fn reset_pulse(port: &mut Box<dyn SerialPort>, device: &Device) -> Result<(), serialport::Error> {
+    // with hardware flow control RTS belongs to the driver
+    let hardware_flow = device.flow_control == FlowControl::Hardware;
+    if hardware_flow && device.reset_pulse.line == ResetLine::Rts {
+        return Err(serialport::Error::new(
+            serialport::ErrorKind::InvalidInput,
+            "RTS is controlled by the hardware flow control",
+        ));
+    }
+    let (dtr, rts) = match device.reset_pulse.line {
+        ResetLine::Dtr => (!device.dtr, device.rts),
+        ResetLine::Rts => (device.dtr, !device.rts),
+        ResetLine::Both => (!device.dtr, !device.rts),
+    };
+    port.write_data_terminal_ready(dtr)?;
+    if !hardware_flow {
+        port.write_request_to_send(rts)?;
+    }
+    std::thread::sleep(device.reset_pulse.duration);
+    port.write_data_terminal_ready(device.dtr)?;
+    if !hardware_flow {
+        port.write_request_to_send(device.rts)?;
+    }
+    Ok(())
+}
+
+fn send_break(port: &mut Box<dyn SerialPort>, duration: Duration) -> Result<(), serialport::Error> {
+    port.set_break()?;
+    std::thread::sleep(duration);
+    port.clear_break()
+}
+
+/// Sends the queued commands as soon as they arrive, independent of the reads.
+fn writer_thread(
+    mut port: Box<dyn SerialPort>,
+    send_rx: Receiver<String>,
+    stop_rx: Receiver<()>,
+    raw_data_tx: Sender<Packet>,
+    device_lock: Arc<RwLock<Device>>,
+    transfer_active: Arc<AtomicBool>,
+    t_zero: Instant,
+) {
+    loop {
+        select! {
+            recv(send_rx) -> cmd => {
+                if let Ok(cmd) = cmd {
+                    // the encoding can be changed while connected
+                    let encoding = device_lock
+                        .read()
+                        .map(|device| device.encoding)
+                        .unwrap_or_default();
+                    // queue the command until a running file transfer is finished,
+                    // otherwise it would end up in the middle of the blocks
+                    while transfer_active.load(Ordering::Relaxed) {
+                        select! {
+                            recv(stop_rx) -> _ => return,
+                            default(TRANSFER_POLL_INTERVAL) => {}
+                        }
+                    }
+                    perform_write(&mut port, cmd, encoding, &raw_data_tx, t_zero);
+                } else {
+                    break;
+                }
+            }
+            recv(stop_rx) -> _ => break,
+        }
+    }
+}
+
+fn perform_write(
+    port: &mut Box<dyn SerialPort>,
+    cmd: String,
+    encoding: TextEncoding,
+    raw_data_tx: &Sender<Packet>,
+    t_zero: Instant,
+) {
+    let raw = encoding.encode(&cmd);
+    if let Err(e) = serial_write(port, &raw) {
+        log::error!("Error sending command: {e}");
+        return;
     }
+
+    // stamped when the command has actually been sent
+    let (relative_time, absolute_time) = timestamps(Instant::now(), t_zero);
+    let packet = Packet {
+        relative_time,
+        absolute_time,
+        direction: SerialDirection::Send,
+        raw,
+        payload: cmd,
+        encoding,
+    };
+    raw_data_tx
+        .send(packet)
+        .expect("failed to send raw data (cmd)");
 }
 
+/// Returns the received bytes, or `None` if reading failed.
 fn perform_reads(
     port: &mut BufReader<Box<dyn SerialPort>>,
+    framer: &mut Framer,
     raw_data_tx: &Sender<Packet>,
+    device: &Device,
     t_zero: Instant,
-) -> bool {
-    let mut buf = "".to_string();
-    match serial_read(port, &mut buf) {
-        Ok(_) => {
-            let delimiter = if buf.contains("\r\n") {
-                "\r\n"
-            } else if buf.contains("\r") {
-                "\r"
-            } else if buf.contains("\n") {
-                "\n"
-            } else {
-                "\0\0"
-            };
-            buf.split_terminator(delimiter).for_each(|s| {
-                let packet = Packet {
-                    relative_time: Instant::now().duration_since(t_zero).as_millis() as f64,
-                    absolute_time: get_epoch_ms() as f64,
-                    direction: SerialDirection::Receive,
-                    payload: s.to_owned(),
-                };
-                raw_data_tx.send(packet).expect("failed to send raw data");
-            });
-            false
+) -> Option<Vec<u8>> {
+    let mut buf = [0; 1024];
+    let (received, mut frames) = match serial_read(port, &mut buf) {
+        Ok(n) => {
+            let frames = framer.push(&buf[..n], Instant::now(), byte_time(device));
+            (buf[..n].to_vec(), frames)
         }
         // Timeout is ok, just means there is no data to read
-        Err(ref e) if e.kind() == std::io::ErrorKind::TimedOut => false,
+        Err(ref e) if e.kind() == std::io::ErrorKind::TimedOut => (vec![], vec![]),
         Err(e) => {
             log::error!("Error reading: {:?}", e);
-            true
+            return None;
+        }
+    };
+    // frames that are complete because the line went silent
+    frames.extend(framer.poll(Instant::now()));
+
+    for frame in frames {
+        let (relative_time, absolute_time) = timestamps(frame.time, t_zero);
+        let packet = Packet {
+            relative_time,
+            absolute_time,
+            direction: SerialDirection::Receive,
+            payload: device.encoding.decode(&frame.payload),
+            raw: frame.raw,
+            encoding: device.encoding,
+        };
+        raw_data_tx.send(packet).expect("failed to send raw data");
+    }
+    Some(received)
+}
+
+/// Feeds the received bytes to the running file transfer and sends its answer.
+fn perform_transfer(
+    port: &mut BufReader<Box<dyn SerialPort>>,
+    transfer: &mut Option<Transfer>,
+    received: &[u8],
+    transfer_lock: &Arc<RwLock<Option<TransferProgress>>>,
+    device: &Device,
+) {
+    let Some(active) = transfer else {
+        return;
+    };
+    let out = active.step(received, Instant::now());
+    if !out.is_empty() {
+        if let Err(err) = serial_write(port.get_mut(), &out) {
+            active.fail(format!("write failed: {err}"));
         }
     }
+    let progress = active.progress();
+    if !active.is_running() {
+        match &progress.state {
+            TransferState::Failed(reason) => {
+                log::error!("Sending {} failed: {}", progress.name, reason)
+            }
+            _ => log::info!("Sent {} ({} bytes)", progress.name, progress.sent),
+        }
+        if let Err(err) = port.get_mut().set_timeout(port_timeout(device)) {
+            log::error!("Error setting timeout: {}", err);
+        }
+        *transfer = None;
+    }
+    if let Ok(mut write_guard) = transfer_lock.write() {
+        *write_guard = Some(progress);
+    }
 }
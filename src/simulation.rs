@@ -0,0 +1,493 @@
+use serde::{Deserialize, Serialize};
+use serialport::{ClearBuffer, DataBits, ErrorKind, FlowControl, Parity, SerialPort, StopBits};
+use std::collections::VecDeque;
+use std::f64::consts::PI;
+use std::fmt;
+use std::io::{Read, Write};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use csv::ReaderBuilder;
+
+use crate::serial::Device;
+
+const SIMULATION_PREFIX: &str = "sim://";
+pub const SIMULATED_SIGNAL: &str = "sim://signal";
+pub const SIMULATED_ECHO: &str = "sim://echo";
+pub const SIMULATED_REPLAY: &str = "sim://replay";
+
+/// Virtual devices that are always shown in the device list.
+pub const SIMULATED_DEVICES: [&str; 3] = [SIMULATED_SIGNAL, SIMULATED_ECHO, SIMULATED_REPLAY];
+
+/// Period of the generated waveforms.
+const SIGNAL_PERIOD: f64 = 2.0;
+
+/// Returns true for the virtual devices like `sim://signal`.
+pub fn is_simulated_device(name: &str) -> bool {
+    name.starts_with(SIMULATION_PREFIX)
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Default)]
+pub enum Waveform {
+    #[default]
+    Sine,
+    Square,
+    Noise,
+    Ramp,
+}
+
+impl Waveform {
+    pub const ALL: [Waveform; 4] = [
+        Waveform::Sine,
+        Waveform::Square,
+        Waveform::Noise,
+        Waveform::Ramp,
+    ];
+}
+
+impl fmt::Display for Waveform {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Waveform::Sine => write!(f, "Sine"),
+            Waveform::Square => write!(f, "Square"),
+            Waveform::Noise => write!(f, "Noise"),
+            Waveform::Ramp => write!(f, "Ramp"),
+        }
+    }
+}
+
+/// Settings of the simulated devices, saved with the device.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Simulation {
+    pub waveform: Waveform,
+    /// Number of generated columns, each one is phase shifted.
+    pub columns: usize,
+    /// Generated lines per second.
+    pub rate: f64,
+    /// Raw traffic CSV (as saved with "Save Raw Traffic") for the replay device.
+    pub replay_file: PathBuf,
+}
+
+impl Default for Simulation {
+    fn default() -> Self {
+        Simulation {
+            waveform: Waveform::Sine,
+            columns: 3,
+            rate: 20.0,
+            replay_file: PathBuf::new(),
+        }
+    }
+}
+
+/// Opens one of the [`SIMULATED_DEVICES`].
+pub fn open_simulated_port(
+    device: &Device,
+    timeout: Duration,
+) -> Result<Box<dyn SerialPort>, serialport::Error> {
+    let source = match device.name.as_str() {
+        SIMULATED_SIGNAL => Source::Signal {
+            waveform: device.simulation.waveform,
+            columns: device.simulation.columns.max(1),
+            rate: device.simulation.rate.max(0.001),
+            samples: 0,
+            noise: 0x2545_f491_4f6c_dd1d,
+        },
+        SIMULATED_ECHO => Source::Echo(VecDeque::new()),
+        SIMULATED_REPLAY => Source::Replay {
+            lines: load_replay(&device.simulation.replay_file)?,
+            next: 0,
+        },
+        _ => {
+            return Err(serialport::Error::new(
+                ErrorKind::NoDevice,
+                format!("unknown simulated device: {}", device.name),
+            ))
+        }
+    };
+    Ok(Box::new(SimulatedPort {
+        name: device.name.clone(),
+        state: Arc::new(Mutex::new(SimulationState {
+            source,
+            pending: VecDeque::new(),
+            start: Instant::now(),
+            dtr: true,
+            rts: true,
+        })),
+        baud_rate: device.baud_rate,
+        data_bits: device.data_bits,
+        flow_control: device.flow_control,
+        parity: device.parity,
+        stop_bits: device.stop_bits,
+        timeout,
+    }))
+}
+
+/// Reads a raw traffic CSV and returns the received lines with their time relative to the
+/// first one. Files without direction column are replayed completely.
+fn load_replay(path: &PathBuf) -> Result<Vec<(Duration, Vec<u8>)>, serialport::Error> {
+    let to_error = |err: csv::Error| {
+        serialport::Error::new(
+            ErrorKind::Io(std::io::ErrorKind::InvalidData),
+            format!("failed to load replay file {}: {}", path.display(), err),
+        )
+    };
+    let mut rdr = ReaderBuilder::new()
+        .has_headers(true)
+        .from_path(path)
+        .map_err(to_error)?;
+    let headers = rdr.headers().map_err(to_error)?.clone();
+    let column = |name: &str| headers.iter().position(|header| header == name);
+    // files exported before the direction column was added have no direction
+    let direction = column("Direction");
+    let traffic = column("Raw Traffic").unwrap_or(headers.len().saturating_sub(1));
+    let mut lines = vec![];
+    let mut first_time = None;
+    for result in rdr.records() {
+        let record = result.map_err(to_error)?;
+        // sent commands and BREAKs are not replayed
+        if direction.is_some_and(|direction| record.get(direction) != Some("RECV")) {
+            continue;
+        }
+        let (Some(Ok(time)), Some(payload)) =
+            (record.get(0).map(str::parse::<f64>), record.get(traffic))
+        else {
+            continue;
+        };
+        let first_time = *first_time.get_or_insert(time);
+        let mut bytes = payload.as_bytes().to_vec();
+        // received payloads are stored without their line ending
+        if !bytes.ends_with(b"\n") {
+            bytes.extend_from_slice(b"\r\n");
+        }
+        let offset = Duration::from_secs_f64((time - first_time).max(0.0) / 1000.0);
+        lines.push((offset, bytes));
+    }
+    log::info!(
+        "Loaded {} lines to replay from {}",
+        lines.len(),
+        path.display()
+    );
+    Ok(lines)
+}
+
+#[derive(Debug)]
+enum Source {
+    Signal {
+        waveform: Waveform,
+        columns: usize,
+        rate: f64,
+        samples: u64,
+        noise: u64,
+    },
+    Echo(VecDeque<u8>),
+    Replay {
+        lines: Vec<(Duration, Vec<u8>)>,
+        next: usize,
+    },
+}
+
+#[derive(Debug)]
+struct SimulationState {
+    source: Source,
+    /// Data that did not fit into the buffer of the last read.
+    pending: VecDeque<u8>,
+    start: Instant,
+    dtr: bool,
+    rts: bool,
+}
+
+impl SimulationState {
+    /// Returns the data that is due at `now`, or the time at which the next data is due.
+    fn poll(&mut self, now: Instant) -> Result<Vec<u8>, Option<Instant>> {
+        let elapsed = now.duration_since(self.start);
+        match &mut self.source {
+            Source::Signal {
+                waveform,
+                columns,
+                rate,
+                samples,
+                noise,
+            } => {
+                let due = (elapsed.as_secs_f64() * *rate) as u64 + 1;
+                if *samples >= due {
+                    let next = Duration::from_secs_f64(*samples as f64 / *rate);
+                    return Err(Some(self.start + next));
+                }
+                let mut data = vec![];
+                for sample in *samples..due {
+                    let t = sample as f64 / *rate;
+                    let values: Vec<String> = (0..*columns)
+                        .map(|column| {
+                            let phase = t / SIGNAL_PERIOD + column as f64 / *columns as f64;
+                            let value = match waveform {
+                                Waveform::Sine => (2.0 * PI * phase).sin(),
+                                Waveform::Square => {
+                                    if phase.fract() < 0.5 {
+                                        1.0
+                                    } else {
+                                        -1.0
+                                    }
+                                }
+                                Waveform::Noise => {
+                                    // xorshift64, no need for a good random generator here
+                                    *noise ^= *noise << 13;
+                                    *noise ^= *noise >> 7;
+                                    *noise ^= *noise << 17;
+                                    (*noise >> 11) as f64 / (1u64 << 53) as f64 * 2.0 - 1.0
+                                }
+                                Waveform::Ramp => phase.fract() * 2.0 - 1.0,
+                            };
+                            format!("{value:.4}")
+                        })
+                        .collect();
+                    data.extend_from_slice(values.join(", ").as_bytes());
+                    data.extend_from_slice(b"\r\n");
+                }
+                *samples = due;
+                Ok(data)
+            }
+            Source::Echo(buffer) => {
+                if buffer.is_empty() {
+                    Err(None)
+                } else {
+                    Ok(buffer.drain(..).collect())
+                }
+            }
+            Source::Replay { lines, next } => {
+                let mut data = vec![];
+                while let Some((offset, line)) = lines.get(*next) {
+                    if *offset > elapsed {
+                        break;
+                    }
+                    data.extend_from_slice(line);
+                    *next += 1;
+                }
+                if !data.is_empty() {
+                    Ok(data)
+                } else {
+                    // nothing left once the whole file has been replayed
+                    Err(lines.get(*next).map(|(offset, _)| self.start + *offset))
+                }
+            }
+        }
+    }
+}
+
+/// A virtual device generating data, echoing everything written to it or replaying a recording.
+/// Clones share their state, so the echo works across them.
+#[derive(Debug)]
+struct SimulatedPort {
+    name: String,
+    state: Arc<Mutex<SimulationState>>,
+    baud_rate: u32,
+    data_bits: DataBits,
+    flow_control: FlowControl,
+    parity: Parity,
+    stop_bits: StopBits,
+    timeout: Duration,
+}
+
+impl SimulatedPort {
+    fn lock(&self) -> std::io::Result<std::sync::MutexGuard<'_, SimulationState>> {
+        self.state
+            .lock()
+            .map_err(|_| std::io::Error::other("simulation state poisoned"))
+    }
+
+    fn is_echo(&self) -> bool {
+        self.name == SIMULATED_ECHO
+    }
+}
+
+impl Read for SimulatedPort {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let deadline = Instant::now() + self.timeout;
+        loop {
+            let now = Instant::now();
+            let mut state = self.lock()?;
+            let next = if state.pending.is_empty() {
+                match state.poll(now) {
+                    Ok(data) => {
+                        state.pending.extend(data);
+                        None
+                    }
+                    Err(next) => next,
+                }
+            } else {
+                None
+            };
+            if !state.pending.is_empty() {
+                // what does not fit is kept for the next read
+                let n = state.pending.len().min(buf.len());
+                for (b, pending) in buf.iter_mut().zip(state.pending.drain(..n)) {
+                    *b = pending;
+                }
+                return Ok(n);
+            }
+            drop(state);
+            if now >= deadline {
+                // the serial thread expects a timeout when there is no data
+                return Err(std::io::ErrorKind::TimedOut.into());
+            }
+            let wake = next.map_or(deadline, |next| next.min(deadline));
+            // writes to the echo device may arrive from a clone
+            let wake = if self.is_echo() {
+                wake.min(now + Duration::from_millis(5))
+            } else {
+                wake
+            };
+            std::thread::sleep(wake.saturating_duration_since(now));
+        }
+    }
+}
+
+impl Write for SimulatedPort {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let mut state = self.lock()?;
+        if let Source::Echo(buffer) = &mut state.source {
+            buffer.extend(buf);
+        }
+        // the other devices ignore what is sent to them
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl SerialPort for SimulatedPort {
+    fn name(&self) -> Option<String> {
+        Some(self.name.clone())
+    }
+
+    fn baud_rate(&self) -> serialport::Result<u32> {
+        Ok(self.baud_rate)
+    }
+
+    fn data_bits(&self) -> serialport::Result<DataBits> {
+        Ok(self.data_bits)
+    }
+
+    fn flow_control(&self) -> serialport::Result<FlowControl> {
+        Ok(self.flow_control)
+    }
+
+    fn parity(&self) -> serialport::Result<Parity> {
+        Ok(self.parity)
+    }
+
+    fn stop_bits(&self) -> serialport::Result<StopBits> {
+        Ok(self.stop_bits)
+    }
+
+    fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    fn set_baud_rate(&mut self, baud_rate: u32) -> serialport::Result<()> {
+        self.baud_rate = baud_rate;
+        Ok(())
+    }
+
+    fn set_data_bits(&mut self, data_bits: DataBits) -> serialport::Result<()> {
+        self.data_bits = data_bits;
+        Ok(())
+    }
+
+    fn set_flow_control(&mut self, flow_control: FlowControl) -> serialport::Result<()> {
+        self.flow_control = flow_control;
+        Ok(())
+    }
+
+    fn set_parity(&mut self, parity: Parity) -> serialport::Result<()> {
+        self.parity = parity;
+        Ok(())
+    }
+
+    fn set_stop_bits(&mut self, stop_bits: StopBits) -> serialport::Result<()> {
+        self.stop_bits = stop_bits;
+        Ok(())
+    }
+
+    fn set_timeout(&mut self, timeout: Duration) -> serialport::Result<()> {
+        self.timeout = timeout;
+        Ok(())
+    }
+
+    fn write_request_to_send(&mut self, level: bool) -> serialport::Result<()> {
+        self.lock()?.rts = level;
+        Ok(())
+    }
+
+    fn write_data_terminal_ready(&mut self, level: bool) -> serialport::Result<()> {
+        self.lock()?.dtr = level;
+        Ok(())
+    }
+
+    // the modem lines are looped back like with a loopback plug: RTS -> CTS, DTR -> DSR and DCD
+
+    fn read_clear_to_send(&mut self) -> serialport::Result<bool> {
+        Ok(self.lock()?.rts)
+    }
+
+    fn read_data_set_ready(&mut self) -> serialport::Result<bool> {
+        Ok(self.lock()?.dtr)
+    }
+
+    fn read_ring_indicator(&mut self) -> serialport::Result<bool> {
+        Ok(false)
+    }
+
+    fn read_carrier_detect(&mut self) -> serialport::Result<bool> {
+        Ok(self.lock()?.dtr)
+    }
+
+    fn bytes_to_read(&self) -> serialport::Result<u32> {
+        let state = self.lock()?;
+        match &state.source {
+            Source::Echo(buffer) => Ok((buffer.len() + state.pending.len()) as u32),
+            _ => Ok(state.pending.len() as u32),
+        }
+    }
+
+    fn bytes_to_write(&self) -> serialport::Result<u32> {
+        Ok(0)
+    }
+
+    fn clear(&self, buffer_to_clear: ClearBuffer) -> serialport::Result<()> {
+        if buffer_to_clear != ClearBuffer::Output {
+            let mut state = self.lock()?;
+            state.pending.clear();
+            if let Source::Echo(buffer) = &mut state.source {
+                buffer.clear();
+            }
+        }
+        Ok(())
+    }
+
+    fn try_clone(&self) -> serialport::Result<Box<dyn SerialPort>> {
+        Ok(Box::new(SimulatedPort {
+            name: self.name.clone(),
+            state: self.state.clone(),
+            baud_rate: self.baud_rate,
+            data_bits: self.data_bits,
+            flow_control: self.flow_control,
+            parity: self.parity,
+            stop_bits: self.stop_bits,
+            timeout: self.timeout,
+        }))
+    }
+
+    fn set_break(&self) -> serialport::Result<()> {
+        Ok(())
+    }
+
+    fn clear_break(&self) -> serialport::Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,397 @@
+use std::fmt;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+const SOH: u8 = 0x01;
+const STX: u8 = 0x02;
+const EOT: u8 = 0x04;
+const ACK: u8 = 0x06;
+const NAK: u8 = 0x15;
+const CAN: u8 = 0x18;
+const SUB: u8 = 0x1A;
+const CRC_START: u8 = b'C';
+
+/// Time the receiver has to start the transfer.
+const START_TIMEOUT: Duration = Duration::from_secs(60);
+/// Time the receiver has to answer a block before it is sent again.
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(10);
+const MAX_RETRIES: u32 = 10;
+/// Bytes written at once by a raw transfer without inter-chunk delay.
+const RAW_BURST: usize = 4096;
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Protocol {
+    /// The file content as it is.
+    #[default]
+    Raw,
+    /// XMODEM with 128 byte blocks and an 8 bit checksum.
+    Xmodem,
+    /// XMODEM with 128 byte blocks and a CRC-16.
+    XmodemCrc,
+    /// XMODEM with 1024 byte blocks and a CRC-16.
+    Xmodem1k,
+    /// YMODEM batch transfer (file name and size in the header block).
+    Ymodem,
+}
+
+impl Protocol {
+    pub const ALL: [Protocol; 5] = [
+        Protocol::Raw,
+        Protocol::Xmodem,
+        Protocol::XmodemCrc,
+        Protocol::Xmodem1k,
+        Protocol::Ymodem,
+    ];
+}
+
+impl fmt::Display for Protocol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Protocol::Raw => write!(f, "Raw"),
+            Protocol::Xmodem => write!(f, "XMODEM"),
+            Protocol::XmodemCrc => write!(f, "XMODEM-CRC"),
+            Protocol::Xmodem1k => write!(f, "XMODEM-1K"),
+            Protocol::Ymodem => write!(f, "YMODEM"),
+        }
+    }
+}
+
+/// A request to send a file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileTransfer {
+    pub path: PathBuf,
+    pub protocol: Protocol,
+    /// Size of the chunks of a raw transfer.
+    pub chunk_size: usize,
+    /// Delay between the chunks of a raw transfer.
+    pub chunk_delay: Duration,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TransferState {
+    Running,
+    Done,
+    Failed(String),
+}
+
+/// Progress of a transfer, shown in the GUI.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TransferProgress {
+    pub name: String,
+    pub protocol: Protocol,
+    pub sent: usize,
+    pub total: usize,
+    pub state: TransferState,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Stage {
+    /// Waiting for the receiver to request the transfer.
+    Start,
+    /// YMODEM header sent, waiting for the ACK.
+    Header,
+    /// YMODEM header acknowledged, waiting for the receiver to request the data.
+    DataStart,
+    /// Data block sent, waiting for the ACK.
+    Data,
+    /// End of transmission sent, waiting for the ACK.
+    Eot,
+    /// YMODEM file done, waiting for the receiver to request the next header.
+    BatchEnd,
+    /// YMODEM empty header sent to end the batch, waiting for the ACK.
+    BatchEndHeader,
+}
+
+/// A file transfer, driven by the serial thread with the received bytes.
+/// [`Transfer::step`] returns the bytes that have to be sent next.
+pub struct Transfer {
+    protocol: Protocol,
+    name: String,
+    data: Vec<u8>,
+    chunk_size: usize,
+    chunk_delay: Duration,
+    /// Bytes acknowledged by the receiver (written for raw transfers).
+    sent: usize,
+    /// Length of the file data in the last sent block.
+    block_len: usize,
+    block: u8,
+    stage: Stage,
+    last_packet: Vec<u8>,
+    retries: u32,
+    deadline: Instant,
+    cancel_count: usize,
+    state: TransferState,
+}
+
+impl Transfer {
+    pub fn new(request: &FileTransfer, now: Instant) -> std::io::Result<Self> {
+        let data = std::fs::read(&request.path)?;
+        let name = request
+            .path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let deadline = if request.protocol == Protocol::Raw {
+            // the first chunk is sent right away
+            now
+        } else {
+            now + START_TIMEOUT
+        };
+        Ok(Transfer {
+            protocol: request.protocol,
+            name,
+            data,
+            chunk_size: request.chunk_size.max(1),
+            chunk_delay: request.chunk_delay,
+            sent: 0,
+            block_len: 0,
+            block: 1,
+            stage: Stage::Start,
+            last_packet: vec![],
+            retries: 0,
+            deadline,
+            cancel_count: 0,
+            state: TransferState::Running,
+        })
+    }
+
+    pub fn progress(&self) -> TransferProgress {
+        TransferProgress {
+            name: self.name.clone(),
+            protocol: self.protocol,
+            sent: self.sent,
+            total: self.data.len(),
+            state: self.state.clone(),
+        }
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.state == TransferState::Running
+    }
+
+    /// Ends the transfer without notifying the receiver, e.g. because the port is gone.
+    pub fn fail(&mut self, reason: impl Into<String>) {
+        if self.is_running() {
+            self.state = TransferState::Failed(reason.into());
+        }
+    }
+
+    /// Cancels the transfer and returns the bytes that tell the receiver.
+    pub fn cancel(&mut self) -> Vec<u8> {
+        if !self.is_running() {
+            return vec![];
+        }
+        self.fail("cancelled");
+        self.cancel_sequence()
+    }
+
+    /// Processes the received bytes and returns the bytes to send.
+    pub fn step(&mut self, received: &[u8], now: Instant) -> Vec<u8> {
+        if !self.is_running() {
+            return vec![];
+        }
+        if self.protocol == Protocol::Raw {
+            return self.step_raw(now);
+        }
+        let mut out = vec![];
+        for &byte in received {
+            if byte == CAN {
+                // a single CAN might be line noise
+                self.cancel_count += 1;
+                if self.cancel_count >= 2 {
+                    self.fail("cancelled by the receiver");
+                }
+            } else {
+                self.cancel_count = 0;
+                out.extend(self.receive(byte, now));
+            }
+            if !self.is_running() {
+                return out;
+            }
+        }
+        if now >= self.deadline {
+            out.extend(self.timeout(now));
+        }
+        out
+    }
+
+    fn step_raw(&mut self, now: Instant) -> Vec<u8> {
+        if now < self.deadline {
+            return vec![];
+        }
+        let len = if self.chunk_delay.is_zero() {
+            self.chunk_size.max(RAW_BURST)
+        } else {
+            self.chunk_size
+        };
+        let end = (self.sent + len).min(self.data.len());
+        let chunk = self.data[self.sent..end].to_vec();
+        self.sent = end;
+        self.deadline = now + self.chunk_delay;
+        if self.sent >= self.data.len() {
+            self.state = TransferState::Done;
+        }
+        chunk
+    }
+
+    fn receive(&mut self, byte: u8, now: Instant) -> Vec<u8> {
+        match (self.stage, byte) {
+            (Stage::Start, b) if b == self.start_byte() => {
+                if self.protocol == Protocol::Ymodem {
+                    self.stage = Stage::Header;
+                    let header = self.header_block();
+                    self.send(header, now)
+                } else {
+                    self.send_data(now)
+                }
+            }
+            (Stage::Header, ACK) => {
+                self.stage = Stage::DataStart;
+                self.retries = 0;
+                self.deadline = now + RESPONSE_TIMEOUT;
+                vec![]
+            }
+            (Stage::DataStart, CRC_START) => self.send_data(now),
+            (Stage::Data, ACK) => {
+                self.sent += self.block_len;
+                self.block = self.block.wrapping_add(1);
+                self.send_data(now)
+            }
+            (Stage::Eot, ACK) => {
+                if self.protocol == Protocol::Ymodem {
+                    self.stage = Stage::BatchEnd;
+                    self.retries = 0;
+                    self.deadline = now + RESPONSE_TIMEOUT;
+                } else {
+                    self.state = TransferState::Done;
+                }
+                vec![]
+            }
+            (Stage::BatchEnd, CRC_START) => {
+                self.stage = Stage::BatchEndHeader;
+                let header = self.block_packet(0, &[0; 128]);
+                self.send(header, now)
+            }
+            (Stage::BatchEndHeader, ACK) => {
+                self.state = TransferState::Done;
+                vec![]
+            }
+            (Stage::Header | Stage::Data | Stage::Eot | Stage::BatchEndHeader, NAK) => {
+                self.resend(now)
+            }
+            // everything else is ignored, it is still shown as received traffic
+            _ => vec![],
+        }
+    }
+
+    fn timeout(&mut self, now: Instant) -> Vec<u8> {
+        match self.stage {
+            Stage::Start => {
+                self.fail("the receiver did not start the transfer");
+                vec![]
+            }
+            Stage::DataStart | Stage::BatchEnd => {
+                self.retries += 1;
+                if self.retries > MAX_RETRIES {
+                    self.fail("no response from the receiver");
+                    return self.cancel_sequence();
+                }
+                self.deadline = now + RESPONSE_TIMEOUT;
+                vec![]
+            }
+            Stage::Header | Stage::Data | Stage::Eot | Stage::BatchEndHeader => self.resend(now),
+        }
+    }
+
+    fn start_byte(&self) -> u8 {
+        if self.protocol == Protocol::Xmodem {
+            NAK
+        } else {
+            CRC_START
+        }
+    }
+
+    /// Sends the next data block, or the end of transmission if all data has been sent.
+    fn send_data(&mut self, now: Instant) -> Vec<u8> {
+        if self.sent >= self.data.len() {
+            self.stage = Stage::Eot;
+            return self.send(vec![EOT], now);
+        }
+        self.stage = Stage::Data;
+        let remaining = self.data.len() - self.sent;
+        // the 1K protocols send the last bytes in a short block if they fit
+        let size = match self.protocol {
+            Protocol::Xmodem1k | Protocol::Ymodem if remaining > 128 => 1024,
+            _ => 128,
+        };
+        self.block_len = remaining.min(size);
+        let mut payload = self.data[self.sent..self.sent + self.block_len].to_vec();
+        payload.resize(size, SUB);
+        let packet = self.block_packet(self.block, &payload);
+        self.send(packet, now)
+    }
+
+    /// YMODEM block 0 with the file name and size.
+    fn header_block(&self) -> Vec<u8> {
+        let mut payload = self.name.as_bytes().to_vec();
+        payload.push(0);
+        payload.extend_from_slice(self.data.len().to_string().as_bytes());
+        payload.push(0);
+        let size = if payload.len() <= 128 { 128 } else { 1024 };
+        payload.resize(size, 0);
+        self.block_packet(0, &payload)
+    }
+
+    fn block_packet(&self, block: u8, payload: &[u8]) -> Vec<u8> {
+        let start = if payload.len() == 1024 { STX } else { SOH };
+        let mut packet = vec![start, block, !block];
+        packet.extend_from_slice(payload);
+        if self.protocol == Protocol::Xmodem {
+            packet.push(payload.iter().fold(0u8, |sum, b| sum.wrapping_add(*b)));
+        } else {
+            packet.extend_from_slice(&crc16_xmodem(payload).to_be_bytes());
+        }
+        packet
+    }
+
+    fn send(&mut self, packet: Vec<u8>, now: Instant) -> Vec<u8> {
+        self.last_packet = packet.clone();
+        self.retries = 0;
+        self.deadline = now + RESPONSE_TIMEOUT;
+        packet
+    }
+
+    fn resend(&mut self, now: Instant) -> Vec<u8> {
+        self.retries += 1;
+        if self.retries > MAX_RETRIES {
+            self.fail("too many retries");
+            return self.cancel_sequence();
+        }
+        self.deadline = now + RESPONSE_TIMEOUT;
+        self.last_packet.clone()
+    }
+
+    fn cancel_sequence(&self) -> Vec<u8> {
+        if self.protocol == Protocol::Raw {
+            vec![]
+        } else {
+            vec![CAN; 3]
+        }
+    }
+}
+
+/// CRC-16 as used by XMODEM (polynomial 0x1021, initial value 0).
+fn crc16_xmodem(data: &[u8]) -> u16 {
+    let mut crc: u16 = 0;
+    for &byte in data {
+        crc ^= (byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}